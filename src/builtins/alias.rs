@@ -0,0 +1,47 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use anyhow::Result;
+
+/// `alias name=value` defines an alias, while `alias name` shows one and `alias` on its own shows
+/// them all, in a form that can be read back in
+pub fn alias_fn(
+    aliases: &mut BTreeMap<String, String>,
+    arguments: Vec<String>,
+    stdout: &mut dyn Write,
+    stderr: &mut dyn Write,
+) -> Result<i32> {
+    let arguments: Vec<&String> = arguments.iter().filter(|arg| *arg != "-p").collect();
+    if arguments.is_empty() {
+        for (name, value) in aliases.iter() {
+            writeln!(stdout, "alias {name}={}", quote(value))?;
+        }
+        return Ok(0);
+    }
+
+    let mut status = 0;
+    for arg in arguments {
+        match arg.split_once('=') {
+            Some((name, _)) if name.is_empty() || name.contains(['/', '$', '`', '\'', '"']) => {
+                writeln!(stderr, "alias: `{name}': invalid alias name")?;
+                status = 1;
+            }
+            Some((name, value)) => {
+                aliases.insert(name.to_string(), value.to_string());
+            }
+            None => match aliases.get(arg.as_str()) {
+                Some(value) => writeln!(stdout, "alias {arg}={}", quote(value))?,
+                None => {
+                    writeln!(stderr, "alias: {arg}: not found")?;
+                    status = 1;
+                }
+            },
+        }
+    }
+    Ok(status)
+}
+
+/// Single quotes a value, where a single quote inside it has to be written as `'\''`
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
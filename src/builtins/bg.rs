@@ -0,0 +1,45 @@
+use std::io::Write;
+
+use anyhow::Result;
+
+use crate::shell::jobs::State;
+use crate::shell::state::Shell;
+
+pub fn bg_fn(
+    shell: &mut Shell,
+    arguments: Vec<String>,
+    stdout: &mut dyn Write,
+    stderr: &mut dyn Write,
+) -> Result<i32> {
+    if shell.terminal.is_none() {
+        writeln!(stderr, "bg: no job control")?;
+        return Ok(1);
+    }
+    let jobs = &mut shell.jobs;
+    let specs: Vec<Option<&str>> = match arguments.is_empty() {
+        true => vec![None],
+        false => arguments.iter().map(|arg| Some(arg.as_str())).collect(),
+    };
+
+    let mut status = 0;
+    for spec in specs {
+        let id = match jobs.find(spec) {
+            Ok(id) => id,
+            Err(e) => {
+                writeln!(stderr, "bg: {e}")?;
+                status = 1;
+                continue;
+            }
+        };
+        let job = jobs.get_mut(id).expect("The job was just found");
+        if job.state() == State::Running {
+            writeln!(stderr, "bg: job {id} already in background")?;
+            continue;
+        }
+
+        job.resume();
+        let command = job.command.clone();
+        writeln!(stdout, "[{id}]{} {command} &", jobs.marker(id))?;
+    }
+    Ok(status)
+}
@@ -4,41 +4,60 @@ use std::path::Path;
 
 use anyhow::Result;
 
-use crate::input::utils::Redirect;
-
-pub fn cd_fn(directory: Vec<String>, buf: Option<&mut Vec<u8>>, redirect: &Redirect) -> Result<()> {
+/// Changes directory, or with `check_only` just reports whether it could. The working directory
+/// belongs to the whole process, so a `cd` on a pipeline's thread mustn't really change it.
+/// `home` is the shell's `HOME`, which is where `~` goes.
+pub fn cd_fn(
+    directory: Vec<String>,
+    check_only: bool,
+    home: Option<&str>,
+    stderr: &mut dyn Write,
+) -> Result<i32> {
     if !directory.is_empty() {
         let dir = directory
             .first()
             .expect("There should be something passed in by this point");
         if dir == "~" {
-            let home_dir = env::var("HOME")?;
-            env::set_current_dir(home_dir)?;
-            return Ok(());
+            let Some(home_dir) = home else {
+                writeln!(stderr, "cd: HOME not set")?;
+                return Ok(1);
+            };
+            if !check_only {
+                env::set_current_dir(home_dir)?;
+            }
+            return Ok(0);
         }
 
         let path = Path::new(dir);
         if path.exists() {
-            env::set_current_dir(path)?;
-        } else {
-            let no_file_fail = format!("cd: {}: No such file or directory\n", dir);
-            match redirect {
-                Redirect::Stderr => {
-                    let buffer = buf.expect("If redirecting we should have a file buffer");
-                    buffer.write_all(no_file_fail.as_bytes())?;
-                }
-                _ => print!("{no_file_fail}"),
+            if !check_only {
+                env::set_current_dir(path)?;
             }
+        } else {
+            writeln!(stderr, "cd: {}: No such file or directory", dir)?;
+            return Ok(1);
         }
     } else {
-        let no_file_passed_in = "No file or directory passed into cd\n";
-        match redirect {
-            Redirect::Stderr => {
-                let buffer = buf.expect("If redirecting we should have a file buffer");
-                buffer.write_all(no_file_passed_in.as_bytes())?;
-            }
-            _ => print!("{no_file_passed_in}"),
-        }
+        writeln!(stderr, "No file or directory passed into cd")?;
+        return Ok(1);
+    }
+    Ok(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn home_comes_from_the_shell() {
+        let mut stderr = vec![];
+        let status = cd_fn(vec!["~".to_string()], true, None, &mut stderr).unwrap();
+        assert_eq!(status, 1);
+        assert_eq!(String::from_utf8(stderr).unwrap(), "cd: HOME not set\n");
+
+        let mut stderr = vec![];
+        let status = cd_fn(vec!["~".to_string()], true, Some("/"), &mut stderr).unwrap();
+        assert_eq!(status, 0);
+        assert!(stderr.is_empty());
     }
-    Ok(())
 }
@@ -0,0 +1,32 @@
+use std::io::Write;
+
+use anyhow::Result;
+
+use crate::shell::jobs::Jobs;
+
+pub fn disown_fn(jobs: &mut Jobs, arguments: Vec<String>, stderr: &mut dyn Write) -> Result<i32> {
+    if arguments.iter().any(|arg| arg == "-a") {
+        for id in jobs.ids() {
+            jobs.remove(id);
+        }
+        return Ok(0);
+    }
+
+    let specs: Vec<Option<&str>> = match arguments.is_empty() {
+        true => vec![None],
+        false => arguments.iter().map(|arg| Some(arg.as_str())).collect(),
+    };
+    let mut status = 0;
+    for spec in specs {
+        match jobs.find(spec) {
+            Ok(id) => {
+                jobs.remove(id);
+            }
+            Err(e) => {
+                writeln!(stderr, "disown: {e}")?;
+                status = 1;
+            }
+        }
+    }
+    Ok(status)
+}
@@ -0,0 +1,53 @@
+use std::io::Write;
+
+use anyhow::Result;
+
+use crate::parser::lexer::is_variable_name;
+use crate::shell::variables::Variables;
+
+pub fn export_fn(
+    variables: &mut Variables,
+    arguments: Vec<String>,
+    stdout: &mut dyn Write,
+    stderr: &mut dyn Write,
+) -> Result<i32> {
+    let unexport = arguments.first().is_some_and(|arg| arg == "-n");
+    let names: Vec<&String> = arguments
+        .iter()
+        .filter(|arg| *arg != "-n" && *arg != "-p")
+        .collect();
+
+    if names.is_empty() {
+        let mut export_display = String::new();
+        for (name, value) in variables.exported() {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+            export_display.push_str(&format!("declare -x {name}=\"{value}\"\n"));
+        }
+        stdout.write_all(export_display.as_bytes())?;
+        return Ok(0);
+    }
+
+    let mut invalid = String::new();
+    for name in names {
+        let (name, value) = match name.split_once('=') {
+            Some((name, value)) => (name, Some(value.to_string())),
+            None => (name.as_str(), None),
+        };
+        if !is_variable_name(name) {
+            invalid.push_str(&format!("export: `{name}': not a valid identifier\n"));
+            continue;
+        }
+
+        if unexport {
+            variables.unexport(name);
+        } else {
+            variables.export(name, value);
+        }
+    }
+
+    if !invalid.is_empty() {
+        stderr.write_all(invalid.as_bytes())?;
+        return Ok(1);
+    }
+    Ok(0)
+}
@@ -0,0 +1,29 @@
+use std::io::Write;
+
+use anyhow::Result;
+
+use crate::shell::state::Shell;
+use crate::subprocesses::job_control::wait_for_foreground;
+
+pub fn fg_fn(
+    shell: &mut Shell,
+    arguments: Vec<String>,
+    stdout: &mut dyn Write,
+    stderr: &mut dyn Write,
+) -> Result<i32> {
+    if shell.terminal.is_none() {
+        writeln!(stderr, "fg: no job control")?;
+        return Ok(1);
+    }
+    let id = match shell.jobs.find(arguments.first().map(String::as_str)) {
+        Ok(id) => id,
+        Err(e) => {
+            writeln!(stderr, "fg: {e}")?;
+            return Ok(1);
+        }
+    };
+
+    let job = shell.jobs.remove(id).expect("The job was just found");
+    writeln!(stdout, "{}", job.command)?;
+    Ok(wait_for_foreground(shell, job, true))
+}
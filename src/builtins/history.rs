@@ -1,16 +1,20 @@
-use crate::input::utils::Redirect;
-use std::env;
 use std::{
     fs::{self, OpenOptions},
     io::Write,
 };
 
-use anyhow::Result;
+use anyhow::{Result, anyhow};
 
+use crate::shell::variables::Variables;
+
+#[derive(Clone)]
 pub struct History {
     list: Vec<String>,
     position: usize,
     append_start: usize,
+    /// What was on the line when moving up from past the newest entry. Only entries starting
+    /// with it are stepped through, and it's put back after moving down past the newest one.
+    typed: String,
 }
 
 impl History {
@@ -19,27 +23,30 @@ impl History {
             list: vec![],
             position: 0,
             append_start: 0,
+            typed: String::new(),
         }
     }
 
-    pub fn read_from_env() -> Result<History> {
-        let histpath = env::var("HISTFILE")?;
+    /// Reads the file the shell's `HISTFILE` names in front of whatever's been added already,
+    /// like by `history -r` in the rc file
+    pub fn read_from_histfile(&mut self, variables: &Variables) -> Result<()> {
+        let histpath = variables
+            .get("HISTFILE")
+            .ok_or_else(|| anyhow!("HISTFILE is not set"))?;
         let file = fs::read_to_string(histpath)?;
 
-        let mut history_list = vec![];
-        for line in file.lines() {
-            history_list.push(line.to_string());
-        }
-
-        Ok(History {
-            position: history_list.len(),
-            append_start: history_list.len(),
-            list: history_list,
-        })
+        let mut history_list: Vec<String> = file.lines().map(str::to_string).collect();
+        self.append_start += history_list.len();
+        history_list.append(&mut self.list);
+        self.list = history_list;
+        self.reset_position();
+        Ok(())
     }
 
-    pub fn write_to_env(&mut self) -> Result<()> {
-        let histpath = env::var("HISTFILE")?;
+    pub fn write_to_histfile(&mut self, variables: &Variables) -> Result<()> {
+        let histpath = variables
+            .get("HISTFILE")
+            .ok_or_else(|| anyhow!("HISTFILE is not set"))?;
         let mut file = OpenOptions::new()
             .create(true)
             .append(true)
@@ -55,33 +62,87 @@ impl History {
 
     pub fn add_entry(&mut self, entry: String) {
         self.list.push(entry);
+        self.reset_position();
+    }
+
+    /// Goes back to the end of the history, past the newest entry
+    pub fn reset_position(&mut self) {
         self.position = self.list.len();
+        self.typed.clear();
     }
 
-    pub fn move_up(&mut self) -> Option<&String> {
-        if self.position == 0 {
-            return None;
+    /// Moves back to the closest older entry starting with what was typed, where `current` is
+    /// what's on the line. Stays put if there isn't one, so the line should be left alone.
+    pub fn move_up(&mut self, current: &str) -> Option<&String> {
+        if self.position >= self.list.len() {
+            self.typed = current.to_string();
         }
-        self.position = self.position.saturating_sub(1);
+        self.position = self.list[..self.position.min(self.list.len())]
+            .iter()
+            .rposition(|entry| entry.starts_with(&self.typed))?;
+        self.list.get(self.position)
+    }
+
+    pub fn get(&self, index: usize) -> Option<&String> {
+        self.list.get(index)
+    }
+
+    /// Carries on moving up and down from an entry that was found some other way
+    pub fn set_position(&mut self, index: usize) {
+        self.position = index.min(self.list.len());
+    }
+
+    /// Finds the closest entry containing `pattern` that's older than `from`, or newer than it
+    /// if it's not `older`
+    pub fn find(&self, pattern: &str, from: usize, older: bool) -> Option<usize> {
+        match older {
+            true => self.list[..from.min(self.list.len())]
+                .iter()
+                .rposition(|entry| entry.contains(pattern)),
+            false => {
+                let start = from.saturating_add(1).min(self.list.len());
+                let offset = self.list[start..]
+                    .iter()
+                    .position(|entry| entry.contains(pattern))?;
+                Some(start + offset)
+            }
+        }
+    }
+
+    /// Moves back to the closest older entry containing `pattern`, staying put if there isn't one
+    pub fn search_up(&mut self, pattern: &str) -> Option<&String> {
+        self.position = self.find(pattern, self.position, true)?;
         self.list.get(self.position)
     }
 
+    /// Moves forward to the closest newer entry containing `pattern`, staying put if there isn't
+    /// one
+    pub fn search_down(&mut self, pattern: &str) -> Option<&String> {
+        self.position = self.find(pattern, self.position, false)?;
+        self.list.get(self.position)
+    }
+
+    /// Moves forward to the closest newer entry starting with what was typed, or past the newest
+    /// one back to what was typed. Gives nothing if it's already past the newest.
     pub fn move_down(&mut self) -> Option<&String> {
-        self.position = self.position.saturating_add(1);
-        if self.position > self.list.len() {
-            self.position = self.list.len();
+        if self.position >= self.list.len() {
             return None;
         }
-        self.list.get(self.position)
+        let start = self.position + 1;
+        self.position = self.list[start..]
+            .iter()
+            .position(|entry| entry.starts_with(&self.typed))
+            .map_or(self.list.len(), |offset| start + offset);
+        self.list.get(self.position).or(Some(&self.typed))
     }
 }
 
 pub fn history_fn(
     history: &mut History,
     arguments: Vec<String>,
-    buf: Option<&mut Vec<u8>>,
-    redirect: &Redirect,
-) -> Result<()> {
+    stdout: &mut dyn Write,
+    stderr: &mut dyn Write,
+) -> Result<i32> {
     let mut history_display = String::new();
 
     if arguments.is_empty() {
@@ -100,15 +161,8 @@ pub fn history_fn(
                     }
                 }
                 None => {
-                    let missing_file = "Need to be sent a file\n";
-                    match redirect {
-                        Redirect::Stderr => {
-                            let buffer = buf.expect("If redirecting we should have a file buffer");
-                            buffer.write_all(missing_file.as_bytes())?;
-                        }
-                        _ => print!("{missing_file}"),
-                    }
-                    return Ok(());
+                    writeln!(stderr, "Need to be sent a file")?;
+                    return Ok(1);
                 }
             },
             "-w" => match arguments.get(1) {
@@ -124,15 +178,8 @@ pub fn history_fn(
                     }
                 }
                 None => {
-                    let missing_file = "Need to be sent a file\n";
-                    match redirect {
-                        Redirect::Stderr => {
-                            let buffer = buf.expect("If redirecting we should have a file buffer");
-                            buffer.write_all(missing_file.as_bytes())?;
-                        }
-                        _ => print!("{missing_file}"),
-                    }
-                    return Ok(());
+                    writeln!(stderr, "Need to be sent a file")?;
+                    return Ok(1);
                 }
             },
             "-a" => match arguments.get(1) {
@@ -147,33 +194,19 @@ pub fn history_fn(
                     history.append_start = history.list.len();
                 }
                 None => {
-                    let missing_file = "Need to be sent a file\n";
-                    match redirect {
-                        Redirect::Stderr => {
-                            let buffer = buf.expect("If redirecting we should have a file buffer");
-                            buffer.write_all(missing_file.as_bytes())?;
-                        }
-                        _ => print!("{missing_file}"),
-                    }
-                    return Ok(());
+                    writeln!(stderr, "Need to be sent a file")?;
+                    return Ok(1);
                 }
             },
             _ => match arg.parse::<usize>() {
                 Ok(history_n) => {
                     if history_n > history.list.len() {
-                        let history_n_too_large = format!(
-                            "Number provided is larger than current history: {}\n",
+                        writeln!(
+                            stderr,
+                            "Number provided is larger than current history: {}",
                             history.list.len()
-                        );
-                        match redirect {
-                            Redirect::Stderr => {
-                                let buffer =
-                                    buf.expect("If redirecting we should have a file buffer");
-                                buffer.write_all(history_n_too_large.as_bytes())?;
-                            }
-                            _ => print!("{history_n_too_large}"),
-                        }
-                        return Ok(());
+                        )?;
+                        return Ok(1);
                     }
 
                     for i in history.list.len() - history_n..history.list.len() {
@@ -185,26 +218,44 @@ pub fn history_fn(
                     }
                 }
                 Err(_) => {
-                    let history_n_parse_fail = "History needs to be provided a number\n";
-                    match redirect {
-                        Redirect::Stderr => {
-                            let buffer = buf.expect("If redirecting we should have a file buffer");
-                            buffer.write_all(history_n_parse_fail.as_bytes())?;
-                        }
-                        _ => print!("{history_n_parse_fail}"),
-                    }
-                    return Ok(());
+                    writeln!(stderr, "History needs to be provided a number")?;
+                    return Ok(1);
                 }
             },
         }
     }
 
-    match redirect {
-        Redirect::Stdout | Redirect::Pipe => {
-            let buffer = buf.expect("If redirecting we should have a file buffer");
-            buffer.write_all(history_display.as_bytes())?;
-        }
-        _ => print!("{history_display}"),
+    stdout.write_all(history_display.as_bytes())?;
+    Ok(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::process;
+
+    use super::*;
+
+    #[test]
+    fn histfile_comes_from_the_shell() {
+        let path = env::temp_dir().join(format!("rustsh-history-test-{}", process::id()));
+        fs::write(&path, "echo one\necho two\n").unwrap();
+        let mut variables = Variables::from_env();
+        variables.set("HISTFILE", path.display().to_string());
+
+        // Entries added before the file is read, like by the rc file, come after its entries
+        let mut history = History::new();
+        history.add_entry("from rc".to_string());
+        history.read_from_histfile(&variables).unwrap();
+        assert_eq!(history.list, ["echo one", "echo two", "from rc"]);
+
+        history.add_entry("echo three".to_string());
+        history.write_to_histfile(&variables).unwrap();
+        let written = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(written, "echo one\necho two\nfrom rc\necho three\n");
+
+        variables.unset("HISTFILE");
+        assert!(History::new().read_from_histfile(&variables).is_err());
     }
-    Ok(())
 }
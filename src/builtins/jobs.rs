@@ -0,0 +1,63 @@
+use std::io::Write;
+
+use anyhow::Result;
+
+use crate::shell::jobs::{Jobs, State};
+
+pub fn jobs_fn(
+    jobs: &mut Jobs,
+    arguments: Vec<String>,
+    stdout: &mut dyn Write,
+    stderr: &mut dyn Write,
+) -> Result<i32> {
+    let mut with_pids = false;
+    let mut pids_only = false;
+    let mut specs = vec![];
+    for arg in &arguments {
+        match arg.as_str() {
+            "-l" => with_pids = true,
+            "-p" => pids_only = true,
+            _ => specs.push(arg.as_str()),
+        }
+    }
+
+    jobs.poll();
+    let mut status = 0;
+    let ids = if specs.is_empty() {
+        jobs.ids()
+    } else {
+        let mut ids = vec![];
+        for spec in specs {
+            match jobs.find(Some(spec)) {
+                Ok(id) => ids.push(id),
+                Err(e) => {
+                    writeln!(stderr, "jobs: {e}")?;
+                    status = 1;
+                }
+            }
+        }
+        ids
+    };
+
+    for id in ids {
+        if pids_only {
+            if let Some(job) = jobs.get_mut(id) {
+                for pid in job.pids() {
+                    writeln!(stdout, "{pid}")?;
+                }
+            }
+        } else {
+            writeln!(stdout, "{}", jobs.describe(id, with_pids))?;
+        }
+
+        // Having been listed counts as being reported, so finished jobs can go
+        let Some(job) = jobs.get_mut(id) else {
+            continue;
+        };
+        job.reported = job.state();
+        if let State::Done(_) = job.reported {
+            jobs.remove(id);
+        }
+    }
+    Ok(status)
+}
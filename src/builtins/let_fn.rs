@@ -0,0 +1,26 @@
+use std::io::Write;
+
+use anyhow::Result;
+
+use crate::expansion::arithmetic::evaluate;
+use crate::shell::state::Shell;
+
+/// Evaluates each argument as an arithmetic expression, succeeding when the last one isn't 0
+pub fn let_fn(shell: &mut Shell, arguments: Vec<String>, stderr: &mut dyn Write) -> Result<i32> {
+    if arguments.is_empty() {
+        writeln!(stderr, "let: expression expected")?;
+        return Ok(1);
+    }
+
+    let mut value = 0;
+    for argument in &arguments {
+        value = match evaluate(shell, argument) {
+            Ok(value) => value,
+            Err(e) => {
+                writeln!(stderr, "let: {e}")?;
+                return Ok(1);
+            }
+        };
+    }
+    Ok(if value != 0 { 0 } else { 1 })
+}
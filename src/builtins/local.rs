@@ -0,0 +1,35 @@
+use std::io::Write;
+
+use anyhow::Result;
+
+use crate::parser::lexer::is_variable_name;
+use crate::shell::variables::Variables;
+
+/// Makes variables local to the function that's running, optionally giving them a value like
+/// `local name=value`
+pub fn local_fn(
+    variables: &mut Variables,
+    arguments: Vec<String>,
+    stderr: &mut dyn Write,
+) -> Result<i32> {
+    let mut status = 0;
+    for arg in arguments {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value.to_string())),
+            None => (arg.as_str(), None),
+        };
+        if !is_variable_name(name) {
+            writeln!(stderr, "local: `{arg}': not a valid identifier")?;
+            status = 1;
+            continue;
+        }
+        if !variables.make_local(name) {
+            writeln!(stderr, "local: can only be used in a function")?;
+            return Ok(1);
+        }
+        if let Some(value) = value {
+            variables.set(name, value);
+        }
+    }
+    Ok(status)
+}
@@ -1,4 +1,27 @@
+pub mod alias;
+pub mod bg;
 pub mod cd;
+pub mod disown;
+pub mod export;
+pub mod fg;
 pub mod history;
+pub mod jobs;
+pub mod let_fn;
+pub mod local;
 pub mod pwd;
+pub mod set;
+pub mod shift;
+pub mod shopt;
+pub mod source;
+pub mod test;
 pub mod type_fn;
+pub mod unalias;
+pub mod unset;
+pub mod wait;
+
+/// Commands the shell runs itself rather than looking for a program
+pub const BUILTINS: [&str; 27] = [
+    "echo", "exit", "type", "cd", "pwd", "history", "export", "unset", "shopt", "set", "shift",
+    "source", ".", "break", "continue", "return", "local", "alias", "unalias", "jobs", "fg", "bg",
+    "wait", "disown", "test", "[", "let",
+];
@@ -3,17 +3,8 @@ use std::io::Write;
 
 use anyhow::Result;
 
-use crate::input::utils::Redirect;
-
-pub fn pwd_fn(buf: Option<&mut Vec<u8>>, redirect: &Redirect) -> Result<()> {
+pub fn pwd_fn(stdout: &mut dyn Write) -> Result<i32> {
     let current_dir = env::current_dir()?;
-    let pwd_display = format!("{} \n", current_dir.display());
-    match redirect {
-        Redirect::Stdout | Redirect::Pipe => {
-            let buffer = buf.expect("If redirecting we should have a file buffer");
-            buffer.write_all(pwd_display.as_bytes())?;
-        }
-        _ => print!("{pwd_display}"),
-    }
-    Ok(())
+    writeln!(stdout, "{}", current_dir.display())?;
+    Ok(0)
 }
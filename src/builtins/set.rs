@@ -0,0 +1,62 @@
+use std::io::Write;
+
+use anyhow::Result;
+
+use crate::shell::options::Options;
+use crate::shell::state::Shell;
+
+/// `set -o name` turns an option on and `set +o name` turns it off, while either one on its own
+/// lists the options. Any other arguments, or everything after `--`, become the positional
+/// parameters.
+pub fn set_fn(
+    shell: &mut Shell,
+    arguments: Vec<String>,
+    stdout: &mut dyn Write,
+    stderr: &mut dyn Write,
+) -> Result<i32> {
+    let options = &mut shell.options;
+    let mut arguments = arguments.iter().peekable();
+    while let Some(flag) = arguments.next() {
+        let value = match flag.as_str() {
+            "-o" => true,
+            "+o" => false,
+            "--" => {
+                shell.positional = arguments.cloned().collect();
+                break;
+            }
+            _ if !flag.starts_with(['-', '+']) => {
+                shell.positional = [flag].into_iter().chain(arguments).cloned().collect();
+                break;
+            }
+            _ => {
+                writeln!(stderr, "set: {flag}: invalid option")?;
+                return Ok(2);
+            }
+        };
+
+        let Some(name) = arguments.next_if(|name| !name.starts_with(['-', '+'])) else {
+            for name in Options::set_names() {
+                let on = options.get(name).unwrap_or_default();
+                match value {
+                    true => writeln!(stdout, "{name:<15}\t{}", if on { "on" } else { "off" })?,
+                    false => writeln!(stdout, "set {}o {name}", if on { '-' } else { '+' })?,
+                }
+            }
+            continue;
+        };
+        match options.get_mut(name) {
+            Some(option) if Options::set_names().contains(&name.as_str()) => *option = value,
+            _ => {
+                writeln!(stderr, "set: {name}: invalid option name")?;
+                return Ok(1);
+            }
+        }
+        // Turning on one editing mode turns off the other
+        match name.as_str() {
+            "emacs" if value => options.vi = false,
+            "vi" if value => options.emacs = false,
+            _ => {}
+        }
+    }
+    Ok(0)
+}
@@ -0,0 +1,27 @@
+use std::io::Write;
+
+use anyhow::Result;
+
+/// Drops the first `n` positional parameters, or just `$1` without a count
+pub fn shift_fn(
+    positional: &mut Vec<String>,
+    arguments: Vec<String>,
+    stderr: &mut dyn Write,
+) -> Result<i32> {
+    let count = match arguments.first() {
+        None => 1,
+        Some(arg) => match arg.parse::<usize>() {
+            Ok(count) => count,
+            Err(_) => {
+                writeln!(stderr, "shift: {arg}: numeric argument required")?;
+                return Ok(1);
+            }
+        },
+    };
+
+    if count > positional.len() {
+        return Ok(1);
+    }
+    positional.drain(..count);
+    Ok(0)
+}
@@ -0,0 +1,55 @@
+use std::io::Write;
+
+use anyhow::Result;
+
+use crate::shell::options::Options;
+
+pub fn shopt_fn(
+    options: &mut Options,
+    arguments: Vec<String>,
+    stdout: &mut dyn Write,
+    stderr: &mut dyn Write,
+) -> Result<i32> {
+    let mut set = None;
+    let mut quiet = false;
+    let mut names = vec![];
+    for arg in &arguments {
+        match arg.as_str() {
+            "-s" => set = Some(true),
+            "-u" => set = Some(false),
+            "-q" => quiet = true,
+            _ => names.push(arg.as_str()),
+        }
+    }
+
+    if let Some(name) = names.iter().find(|name| !Options::names().contains(name)) {
+        writeln!(stderr, "shopt: {name}: invalid shell option name")?;
+        return Ok(1);
+    }
+
+    if let Some(value) = set {
+        for name in names {
+            if let Some(option) = options.get_mut(name) {
+                *option = value;
+            }
+        }
+        return Ok(0);
+    }
+
+    if names.is_empty() {
+        names = Options::names().to_vec();
+    }
+    let mut shopt_display = String::new();
+    let mut all_on = true;
+    for name in names {
+        let on = options.get(name).unwrap_or_default();
+        all_on &= on;
+        let state = if on { "on" } else { "off" };
+        shopt_display.push_str(&format!("{name:<15}\t{state}\n"));
+    }
+
+    if !quiet {
+        stdout.write_all(shopt_display.as_bytes())?;
+    }
+    Ok(if all_on { 0 } else { 1 })
+}
@@ -0,0 +1,59 @@
+use std::env;
+use std::fs::File;
+use std::io::{BufReader, Write};
+use std::mem;
+use std::path::PathBuf;
+
+use anyhow::Result;
+
+use crate::input::script::run_script;
+use crate::shell::state::Shell;
+use crate::subprocesses::executor::Outcome;
+use crate::subprocesses::fds::Fds;
+
+/// Runs a file's commands in the current shell, so anything it sets up is still there afterwards.
+/// Any extra arguments are the positional parameters while it runs.
+pub fn source_fn(shell: &mut Shell, arguments: Vec<String>, fds: &Fds) -> Result<Outcome> {
+    let mut stderr = fds.writer(2);
+    let Some((name, arguments)) = arguments.split_first() else {
+        writeln!(
+            stderr,
+            "{}source: filename argument required",
+            shell.error_prefix()
+        )?;
+        return Ok(Outcome::Status(2));
+    };
+
+    let file = match File::open(find_file(name, shell.variables.get("PATH"))) {
+        Ok(file) => file,
+        Err(e) => {
+            writeln!(stderr, "{}{name}: {e}", shell.error_prefix())?;
+            return Ok(Outcome::Status(1));
+        }
+    };
+
+    let caller_arguments = match arguments.is_empty() {
+        true => None,
+        false => Some(mem::replace(&mut shell.positional, arguments.to_vec())),
+    };
+    shell.call_depth += 1;
+    let outcome = run_script(shell, BufReader::new(file), name, fds);
+    shell.call_depth -= 1;
+    if let Some(caller_arguments) = caller_arguments {
+        shell.positional = caller_arguments;
+    }
+    outcome
+}
+
+/// A name without a slash is looked for in `$PATH` first and then in the current directory
+fn find_file(name: &str, path: Option<&str>) -> PathBuf {
+    if !name.contains('/')
+        && let Some(path) = path
+        && let Some(found) = env::split_paths(path)
+            .map(|dir| dir.join(name))
+            .find(|candidate| candidate.is_file())
+    {
+        return found;
+    }
+    PathBuf::from(name)
+}
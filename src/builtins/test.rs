@@ -0,0 +1,321 @@
+use std::ffi::CString;
+use std::fs::{self, Metadata};
+use std::io::Write;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+
+use anyhow::Result;
+use regex::Regex;
+
+use crate::expansion::pattern::pattern_matches;
+use crate::expansion::utils::{expand_pattern, expand_regex, expand_word_to_string};
+use crate::parser::ast::Conditional;
+use crate::shell::state::Shell;
+use crate::shell::variables::Variables;
+
+/// Tests that take one operand, like `-f path`
+pub const UNARY_OPERATORS: [&str; 24] = [
+    "-a", "-b", "-c", "-d", "-e", "-f", "-g", "-h", "-k", "-n", "-p", "-r", "-s", "-t", "-u", "-v",
+    "-w", "-x", "-z", "-G", "-L", "-N", "-O", "-S",
+];
+
+/// Tests that compare two operands, like `a = b`. `[[ ]]` has `=~` on top of these.
+pub const BINARY_OPERATORS: [&str; 14] = [
+    "=", "==", "!=", "<", ">", "-eq", "-ne", "-lt", "-le", "-gt", "-ge", "-nt", "-ot", "-ef",
+];
+
+/// `test` and `[`, which succeed when the expression is true, fail when it's false and give 2
+/// when it's malformed. `[` needs a `]` as its last argument.
+pub fn test_fn(
+    command: &str,
+    variables: &Variables,
+    mut arguments: Vec<String>,
+    stderr: &mut dyn Write,
+) -> Result<i32> {
+    if command == "[" {
+        if arguments.last().map(String::as_str) != Some("]") {
+            writeln!(stderr, "[: missing `]'")?;
+            return Ok(2);
+        }
+        arguments.pop();
+    }
+
+    let mut parser = TestParser {
+        arguments: &arguments,
+        position: 0,
+        variables,
+    };
+    let result = match arguments.is_empty() {
+        true => Ok(false),
+        false => parser.parse_or().and_then(|result| parser.finish(result)),
+    };
+    match result {
+        Ok(true) => Ok(0),
+        Ok(false) => Ok(1),
+        Err(e) => {
+            writeln!(stderr, "{command}: {e}")?;
+            Ok(2)
+        }
+    }
+}
+
+/// Works through the arguments to `test`, where `-o` binds less tightly than `-a`, which binds
+/// less tightly than `!`
+struct TestParser<'a> {
+    arguments: &'a [String],
+    position: usize,
+    variables: &'a Variables,
+}
+
+impl<'a> TestParser<'a> {
+    fn peek_at(&self, offset: usize) -> Option<&'a str> {
+        self.arguments
+            .get(self.position + offset)
+            .map(String::as_str)
+    }
+
+    fn next(&mut self) -> Result<&'a str, String> {
+        let argument = self
+            .arguments
+            .get(self.position)
+            .ok_or("argument expected")?;
+        self.position += 1;
+        Ok(argument)
+    }
+
+    /// Fails if there are arguments left over once the expression is done
+    fn finish(&self, result: bool) -> Result<bool, String> {
+        match self.peek_at(0) {
+            None => Ok(result),
+            Some(_) if self.arguments.len() == 2 => {
+                Err(format!("{}: unary operator expected", self.arguments[0]))
+            }
+            Some(_) if self.arguments.len() == 3 => {
+                Err(format!("{}: binary operator expected", self.arguments[1]))
+            }
+            Some(_) => Err("too many arguments".to_string()),
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<bool, String> {
+        let mut result = self.parse_and()?;
+        while self.peek_at(0) == Some("-o") {
+            self.position += 1;
+            // Both sides are always parsed, even once the answer is known
+            result = self.parse_and()? || result;
+        }
+        Ok(result)
+    }
+
+    fn parse_and(&mut self) -> Result<bool, String> {
+        let mut result = self.parse_not()?;
+        while self.peek_at(0) == Some("-a") {
+            self.position += 1;
+            result = self.parse_not()? && result;
+        }
+        Ok(result)
+    }
+
+    fn parse_not(&mut self) -> Result<bool, String> {
+        // With a binary operator after it, `!` is just a string being compared
+        let is_binary = self
+            .peek_at(1)
+            .is_some_and(|op| BINARY_OPERATORS.contains(&op) && self.peek_at(2).is_some());
+        if self.peek_at(0) == Some("!") && !is_binary {
+            self.position += 1;
+            return Ok(!self.parse_not()?);
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<bool, String> {
+        if let (Some(left), Some(op), Some(right)) =
+            (self.peek_at(0), self.peek_at(1), self.peek_at(2))
+            && BINARY_OPERATORS.contains(&op)
+        {
+            self.position += 3;
+            return binary_test(op, left, right);
+        }
+
+        let argument = self.next()?;
+        if argument == "(" {
+            let result = self.parse_or()?;
+            if self.next().ok() != Some(")") {
+                return Err("`)' expected".to_string());
+            }
+            return Ok(result);
+        }
+        if UNARY_OPERATORS.contains(&argument) && self.peek_at(0).is_some() {
+            let operand = self.next()?;
+            return Ok(unary_test(argument, operand, self.variables));
+        }
+        Ok(!argument.is_empty())
+    }
+}
+
+/// A test like `-f path` or `-n string`
+pub fn unary_test(op: &str, operand: &str, variables: &Variables) -> bool {
+    match op {
+        "-n" => !operand.is_empty(),
+        "-z" => operand.is_empty(),
+        "-v" => variables.get(operand).is_some(),
+        "-t" => operand
+            .trim()
+            .parse::<i32>()
+            .is_ok_and(|fd| unsafe { libc::isatty(fd) } == 1),
+        "-r" => access(operand, libc::R_OK),
+        "-w" => access(operand, libc::W_OK),
+        "-x" => access(operand, libc::X_OK),
+        // Everything else is about what sort of file it is
+        "-h" | "-L" => fs::symlink_metadata(operand).is_ok_and(|m| m.file_type().is_symlink()),
+        _ => fs::metadata(operand).is_ok_and(|metadata| file_test(op, &metadata)),
+    }
+}
+
+fn file_test(op: &str, metadata: &Metadata) -> bool {
+    let file_type = metadata.file_type();
+    let mode = metadata.mode();
+    match op {
+        "-a" | "-e" => true,
+        "-b" => file_type.is_block_device(),
+        "-c" => file_type.is_char_device(),
+        "-d" => file_type.is_dir(),
+        "-f" => file_type.is_file(),
+        "-p" => file_type.is_fifo(),
+        "-S" => file_type.is_socket(),
+        "-s" => metadata.len() > 0,
+        "-g" => mode & libc::S_ISGID != 0,
+        "-u" => mode & libc::S_ISUID != 0,
+        "-k" => mode & libc::S_ISVTX != 0,
+        "-O" => metadata.uid() == unsafe { libc::geteuid() },
+        "-G" => metadata.gid() == unsafe { libc::getegid() },
+        // Modified since it was last read
+        "-N" => metadata.mtime() > metadata.atime(),
+        _ => false,
+    }
+}
+
+/// Whether the shell can read, write or run a file, going by the same rules the kernel uses
+fn access(path: &str, mode: libc::c_int) -> bool {
+    let Ok(path) = CString::new(path) else {
+        return false;
+    };
+    unsafe { libc::access(path.as_ptr(), mode) == 0 }
+}
+
+/// A comparison like `a = b`, `1 -lt 2` or `old -ot new`
+pub fn binary_test(op: &str, left: &str, right: &str) -> Result<bool, String> {
+    let integers = || Ok::<_, String>((integer(left)?, integer(right)?));
+    let modified = |path| fs::metadata(path).map(|metadata| metadata.mtime());
+    Ok(match op {
+        "=" | "==" => left == right,
+        "!=" => left != right,
+        "<" => left < right,
+        ">" => left > right,
+        "-eq" => integers().map(|(left, right)| left == right)?,
+        "-ne" => integers().map(|(left, right)| left != right)?,
+        "-lt" => integers().map(|(left, right)| left < right)?,
+        "-le" => integers().map(|(left, right)| left <= right)?,
+        "-gt" => integers().map(|(left, right)| left > right)?,
+        "-ge" => integers().map(|(left, right)| left >= right)?,
+        // A file that doesn't exist is older than any that does
+        "-nt" => match (modified(left), modified(right)) {
+            (Ok(left), Ok(right)) => left > right,
+            (left, right) => left.is_ok() && right.is_err(),
+        },
+        "-ot" => match (modified(left), modified(right)) {
+            (Ok(left), Ok(right)) => left < right,
+            (left, right) => left.is_err() && right.is_ok(),
+        },
+        "-ef" => match (fs::metadata(left), fs::metadata(right)) {
+            (Ok(left), Ok(right)) => left.dev() == right.dev() && left.ino() == right.ino(),
+            _ => false,
+        },
+        _ => return Err(format!("{op}: binary operator expected")),
+    })
+}
+
+fn integer(text: &str) -> Result<i64, String> {
+    text.trim()
+        .parse()
+        .map_err(|_| format!("{text}: integer expression expected"))
+}
+
+/// Runs `[[ expression ]]`, giving 0 when it's true, 1 when it's false and 2 when it can't be
+/// worked out, like a bad regex
+pub fn conditional_fn(
+    shell: &mut Shell,
+    expression: &Conditional,
+    stderr: &mut dyn Write,
+) -> Result<i32> {
+    match evaluate(shell, expression)? {
+        Ok(true) => Ok(0),
+        Ok(false) => Ok(1),
+        Err(e) => {
+            writeln!(stderr, "{}{e}", shell.error_prefix())?;
+            Ok(2)
+        }
+    }
+}
+
+/// Works out an expression, where the inner result fails for a malformed one and the outer one
+/// for an expansion that went wrong. `&&` and `||` only expand their right side when they need
+/// to.
+fn evaluate(shell: &mut Shell, expression: &Conditional) -> Result<Result<bool, String>> {
+    Ok(Ok(match expression {
+        Conditional::Word(word) => !expand_word_to_string(shell, word)?.is_empty(),
+        Conditional::Unary(op, word) => {
+            let operand = expand_word_to_string(shell, word)?;
+            unary_test(op, &operand, &shell.variables)
+        }
+        Conditional::Binary(op, left, right) => {
+            let left = expand_word_to_string(shell, left)?;
+            match op.as_str() {
+                // The right side is a pattern, unless it's quoted
+                "=" | "==" | "!=" => {
+                    let matches = pattern_matches(&expand_pattern(shell, right)?, &left);
+                    matches == (op != "!=")
+                }
+                "=~" => {
+                    let regex = expand_regex(shell, right)?;
+                    let Ok(regex) = Regex::new(&regex) else {
+                        return Ok(Err(format!("{regex}: invalid regular expression")));
+                    };
+                    regex_test(shell, &regex, &left)
+                }
+                _ => {
+                    let right = expand_word_to_string(shell, right)?;
+                    match binary_test(op, &left, &right) {
+                        Ok(result) => result,
+                        Err(e) => return Ok(Err(e)),
+                    }
+                }
+            }
+        }
+        Conditional::Not(expression) => match evaluate(shell, expression)? {
+            Ok(result) => !result,
+            Err(e) => return Ok(Err(e)),
+        },
+        Conditional::And(left, right) => match evaluate(shell, left)? {
+            Ok(true) => return evaluate(shell, right),
+            result => return Ok(result),
+        },
+        Conditional::Or(left, right) => match evaluate(shell, left)? {
+            Ok(false) => return evaluate(shell, right),
+            result => return Ok(result),
+        },
+    }))
+}
+
+/// Matches a regex, putting the whole match and each group in `BASH_REMATCH`
+fn regex_test(shell: &mut Shell, regex: &Regex, text: &str) -> bool {
+    let Some(captures) = regex.captures(text) else {
+        shell.variables.set_array("BASH_REMATCH", vec![]);
+        return false;
+    };
+    let groups = captures
+        .iter()
+        .map(|group| group.map_or("", |group| group.as_str()).to_string())
+        .collect();
+    shell.variables.set_array("BASH_REMATCH", groups);
+    true
+}
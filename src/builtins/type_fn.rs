@@ -1,25 +1,46 @@
-use crate::input::utils::Redirect;
 use std::io::Write;
 
+use crate::builtins::BUILTINS;
+use crate::parser::lexer::RESERVED_WORDS;
+use crate::shell::state::Shell;
 use crate::subprocesses::utils::path_search;
 
 use anyhow::Result;
 
-pub fn type_fn(command: &str, buf: Option<&mut Vec<u8>>, redirect: &Redirect) -> Result<()> {
+pub fn type_fn(
+    shell: &Shell,
+    command: &str,
+    stdout: &mut dyn Write,
+    stderr: &mut dyn Write,
+) -> Result<i32> {
     match command {
-        "echo" | "type" | "exit" | "pwd" | "cd" | "history" => {
-            let shell_builtin = format!("{} is a shell builtin\n", command);
-            match redirect {
-                Redirect::Stdout | Redirect::Pipe => {
-                    let buffer = buf.expect("If redirecting we should have a file buffer");
-                    buffer.write_all(shell_builtin.as_bytes())?;
-                }
-                _ => print!("{shell_builtin}"),
-            }
+        _ if shell.aliases.contains_key(command) => {
+            writeln!(
+                stdout,
+                "{} is aliased to `{}'",
+                command, shell.aliases[command]
+            )?;
+        }
+        _ if RESERVED_WORDS.contains(&command) => {
+            writeln!(stdout, "{} is a shell keyword", command)?;
+        }
+        _ if shell.functions.contains_key(command) => {
+            writeln!(stdout, "{} is a function", command)?;
+            writeln!(stdout, "{} () {}", command, shell.functions[command])?;
+        }
+        _ if BUILTINS.contains(&command) => {
+            writeln!(stdout, "{} is a shell builtin", command)?;
         }
         _ => {
-            let _ = path_search(command, true, buf, redirect)?;
+            let path = shell.variables.get("PATH").unwrap_or_default();
+            match path_search(command, path)? {
+                Some(path) => writeln!(stdout, "{} is {}", command, path.display())?,
+                None => {
+                    writeln!(stderr, "{}: not found", command)?;
+                    return Ok(1);
+                }
+            }
         }
     }
-    Ok(())
+    Ok(0)
 }
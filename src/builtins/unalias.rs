@@ -0,0 +1,29 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use anyhow::Result;
+
+/// Removes the named aliases, or all of them with `-a`
+pub fn unalias_fn(
+    aliases: &mut BTreeMap<String, String>,
+    arguments: Vec<String>,
+    stderr: &mut dyn Write,
+) -> Result<i32> {
+    if arguments.iter().any(|arg| arg == "-a") {
+        aliases.clear();
+        return Ok(0);
+    }
+    if arguments.is_empty() {
+        writeln!(stderr, "unalias: usage: unalias [-a] name [name ...]")?;
+        return Ok(2);
+    }
+
+    let mut status = 0;
+    for name in arguments {
+        if aliases.remove(&name).is_none() {
+            writeln!(stderr, "unalias: {name}: not found")?;
+            status = 1;
+        }
+    }
+    Ok(status)
+}
@@ -0,0 +1,33 @@
+use std::io::Write;
+
+use anyhow::Result;
+
+use crate::parser::lexer::is_variable_name;
+use crate::shell::state::Shell;
+
+/// Unsets variables, or functions with `-f`. Without either flag a name that isn't a variable is
+/// taken to be a function.
+pub fn unset_fn(shell: &mut Shell, arguments: Vec<String>, stderr: &mut dyn Write) -> Result<i32> {
+    let functions_only = arguments.iter().any(|arg| arg == "-f");
+    let variables_only = arguments.iter().any(|arg| arg == "-v");
+
+    let mut invalid = String::new();
+    for name in arguments.iter().filter(|arg| *arg != "-v" && *arg != "-f") {
+        if functions_only {
+            shell.functions.remove(name);
+        } else if is_variable_name(name) {
+            if !variables_only && shell.variables.get(name).is_none() {
+                shell.functions.remove(name);
+            }
+            shell.variables.unset(name);
+        } else {
+            invalid.push_str(&format!("unset: `{name}': not a valid identifier\n"));
+        }
+    }
+
+    if !invalid.is_empty() {
+        stderr.write_all(invalid.as_bytes())?;
+        return Ok(1);
+    }
+    Ok(0)
+}
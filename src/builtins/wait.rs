@@ -0,0 +1,54 @@
+use std::io::Write;
+
+use anyhow::Result;
+
+use crate::shell::jobs::{Jobs, State};
+
+/// Waits for a job until it finishes, or stops, in which case it stays in the table
+fn wait_for_job(jobs: &mut Jobs, id: usize) -> i32 {
+    let Some(mut job) = jobs.remove(id) else {
+        return 127;
+    };
+    let state = job.wait();
+    let status = job.status();
+    if state == State::Stopped {
+        jobs.insert(job);
+    }
+    status
+}
+
+pub fn wait_fn(jobs: &mut Jobs, arguments: Vec<String>, stderr: &mut dyn Write) -> Result<i32> {
+    // A subshell's jobs belong to its parent, so there's nothing it can wait for
+    if jobs.is_snapshot() {
+        *jobs = Jobs::default();
+    }
+    if arguments.is_empty() {
+        for id in jobs.ids() {
+            wait_for_job(jobs, id);
+        }
+        return Ok(0);
+    }
+
+    let mut status = 0;
+    for arg in &arguments {
+        let id = if arg.starts_with('%') {
+            jobs.find(Some(arg))
+        } else {
+            match arg.parse::<libc::pid_t>() {
+                Ok(pid) => jobs
+                    .find_pid(pid)
+                    .ok_or_else(|| format!("pid {pid} is not a child of this shell")),
+                Err(_) => Err(format!("`{arg}': not a pid or valid job spec")),
+            }
+        };
+
+        status = match id {
+            Ok(id) => wait_for_job(jobs, id),
+            Err(e) => {
+                writeln!(stderr, "wait: {e}")?;
+                127
+            }
+        };
+    }
+    Ok(status)
+}
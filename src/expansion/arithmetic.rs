@@ -0,0 +1,493 @@
+use anyhow::{Result, anyhow};
+
+use crate::parser::lexer::is_variable_name;
+use crate::shell::state::Shell;
+
+/// How deeply variables holding expressions can refer to each other before giving up. This has to
+/// stay well within what the stack can take, including on a pipeline's builtin thread, so that a
+/// variable referring to itself is an error rather than a crash.
+const MAX_DEPTH: usize = 32;
+
+// Longest operators first so that `<<=` wins over `<<` and `<`
+const OPERATORS: [&str; 39] = [
+    "<<=", ">>=", "**", "++", "--", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "*=", "/=",
+    "%=", "+=", "-=", "&=", "^=", "|=", "+", "-", "*", "/", "%", "<", ">", "&", "|", "^", "!", "~",
+    "?", ":", "=", "(", ")", ",",
+];
+
+/// Binary operators from the loosest to the tightest binding, where the ones on the same level
+/// bind equally tightly
+const PRECEDENCE: [&[&str]; 11] = [
+    &["||"],
+    &["&&"],
+    &["|"],
+    &["^"],
+    &["&"],
+    &["==", "!="],
+    &["<", ">", "<=", ">="],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+    &["**"],
+];
+
+#[derive(Clone, PartialEq, Debug)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Operator(&'static str),
+}
+
+/// Works out an integer expression like `$((x * 2 + 1))` or `((i++))`, assigning to any variables
+/// it changes along the way
+pub fn evaluate(shell: &mut Shell, expression: &str) -> Result<i64> {
+    evaluate_nested(shell, expression, 0)
+}
+
+fn evaluate_nested(shell: &mut Shell, expression: &str, depth: usize) -> Result<i64> {
+    if depth > MAX_DEPTH {
+        return Err(anyhow!("{expression}: expression recursion level exceeded"));
+    }
+    // Nothing at all counts as 0, like a variable that's never been set
+    if expression.trim().is_empty() {
+        return Ok(0);
+    }
+
+    let (tokens, starts) = tokenize(expression)?;
+    let mut arithmetic = Arithmetic {
+        shell,
+        expression,
+        tokens,
+        starts,
+        position: 0,
+        skipping: false,
+        depth,
+    };
+    let value = arithmetic.parse_comma()?;
+    if arithmetic.position < arithmetic.tokens.len() {
+        return Err(arithmetic.syntax_error());
+    }
+    Ok(value)
+}
+
+/// Splits an expression into numbers, names and operators, along with where each one starts so
+/// errors can point at them
+fn tokenize(expression: &str) -> Result<(Vec<Token>, Vec<usize>)> {
+    let mut tokens = vec![];
+    let mut starts = vec![];
+    let mut rest = expression;
+
+    loop {
+        rest = rest.trim_start();
+        let start = expression.len() - rest.len();
+        let Some(c) = rest.chars().next() else {
+            break;
+        };
+
+        let token = if c.is_ascii_alphanumeric() || c == '_' {
+            let length = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '_' | '#' | '@')))
+                .unwrap_or(rest.len());
+            let (text, remaining) = rest.split_at(length);
+            rest = remaining;
+            match c.is_ascii_digit() {
+                true => Token::Number(parse_number(text)?),
+                false if is_variable_name(text) => Token::Name(text.to_string()),
+                false => {
+                    let error_token = &expression[start..];
+                    return Err(anyhow!(
+                        "{expression}: syntax error: invalid arithmetic operator (error token is \"{error_token}\")"
+                    ));
+                }
+            }
+        } else if let Some(operator) = OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+            rest = &rest[operator.len()..];
+            Token::Operator(operator)
+        } else {
+            let error_token = &expression[start..];
+            return Err(anyhow!(
+                "{expression}: syntax error: invalid arithmetic operator (error token is \"{error_token}\")"
+            ));
+        };
+        tokens.push(token);
+        starts.push(start);
+    }
+    Ok((tokens, starts))
+}
+
+/// Reads a number in decimal, octal with a leading `0`, hex with a leading `0x`, or any base from
+/// 2 to 64 written like `16#ff`
+fn parse_number(text: &str) -> Result<i64> {
+    let (base, digits) = if let Some((base, digits)) = text.split_once('#') {
+        match base.parse::<u32>() {
+            Ok(base) if (2..=64).contains(&base) => (base, digits),
+            _ => return Err(anyhow!("{text}: invalid arithmetic base")),
+        }
+    } else if let Some(digits) = text.strip_prefix("0x").or(text.strip_prefix("0X")) {
+        (16, digits)
+    } else if text.len() > 1
+        && let Some(digits) = text.strip_prefix('0')
+    {
+        (8, digits)
+    } else {
+        (10, text)
+    };
+
+    let too_great = || anyhow!("{text}: value too great for base (error token is \"{text}\")");
+    if digits.is_empty() {
+        return Err(too_great());
+    }
+    let mut value: i64 = 0;
+    for c in digits.chars() {
+        // Up to base 36 letters can be either case, and past that lowercase comes first
+        let digit = match c {
+            '0'..='9' => c as u32 - '0' as u32,
+            'a'..='z' => c as u32 - 'a' as u32 + 10,
+            'A'..='Z' if base <= 36 => c as u32 - 'A' as u32 + 10,
+            'A'..='Z' => c as u32 - 'A' as u32 + 36,
+            '@' => 62,
+            '_' => 63,
+            _ => return Err(too_great()),
+        };
+        if digit >= base {
+            return Err(too_great());
+        }
+        value = value.wrapping_mul(base as i64).wrapping_add(digit as i64);
+    }
+    Ok(value)
+}
+
+/// Parses and evaluates an expression in one go, following C's precedence rules
+struct Arithmetic<'a> {
+    shell: &'a mut Shell,
+    expression: &'a str,
+    tokens: Vec<Token>,
+    starts: Vec<usize>,
+    position: usize,
+    /// Set while going through a part of the expression that isn't used, like the right of
+    /// `0 && x++`, which is checked but doesn't assign anything or fail on dividing by 0
+    skipping: bool,
+    depth: usize,
+}
+
+impl Arithmetic<'_> {
+    fn peek_at(&self, offset: usize) -> Option<&Token> {
+        self.tokens.get(self.position + offset)
+    }
+
+    fn peek_operator(&self, operator: &str) -> bool {
+        matches!(self.peek_at(0), Some(Token::Operator(op)) if *op == operator)
+    }
+
+    fn expect_operator(&mut self, operator: &str) -> Result<()> {
+        if !self.peek_operator(operator) {
+            return Err(self.syntax_error());
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    fn syntax_error(&self) -> anyhow::Error {
+        let expression = self.expression;
+        match self.starts.get(self.position) {
+            Some(start) => anyhow!(
+                "{expression}: syntax error in expression (error token is \"{}\")",
+                &expression[*start..]
+            ),
+            None => anyhow!("{expression}: syntax error: operand expected"),
+        }
+    }
+
+    fn error(&self, message: &str) -> anyhow::Error {
+        let start = self.starts[self.position.saturating_sub(1)];
+        anyhow!(
+            "{}: {message} (error token is \"{}\")",
+            self.expression,
+            &self.expression[start..]
+        )
+    }
+
+    /// A variable's value, which can itself be an expression
+    fn value(&mut self, name: &str) -> Result<i64> {
+        let value = self
+            .shell
+            .variables
+            .get(name)
+            .unwrap_or_default()
+            .to_string();
+        evaluate_nested(self.shell, &value, self.depth + 1)
+    }
+
+    fn assign(&mut self, name: &str, value: i64) {
+        if !self.skipping {
+            self.shell.variables.set(name, value.to_string());
+        }
+    }
+
+    /// Runs `inner` with assignments and errors turned off when `skip` is set
+    fn maybe_skip(
+        &mut self,
+        skip: bool,
+        inner: impl FnOnce(&mut Self) -> Result<i64>,
+    ) -> Result<i64> {
+        let skipping = self.skipping;
+        self.skipping |= skip;
+        let value = inner(self);
+        self.skipping = skipping;
+        value
+    }
+
+    fn parse_comma(&mut self) -> Result<i64> {
+        let mut value = self.parse_assignment()?;
+        while self.peek_operator(",") {
+            self.position += 1;
+            value = self.parse_assignment()?;
+        }
+        Ok(value)
+    }
+
+    fn parse_assignment(&mut self) -> Result<i64> {
+        const ASSIGNMENTS: [&str; 11] = [
+            "=", "*=", "/=", "%=", "+=", "-=", "<<=", ">>=", "&=", "^=", "|=",
+        ];
+        let (Some(Token::Name(name)), Some(Token::Operator(op))) =
+            (self.peek_at(0), self.peek_at(1))
+        else {
+            return self.parse_ternary();
+        };
+        if !ASSIGNMENTS.contains(op) {
+            return self.parse_ternary();
+        }
+        let (name, op) = (name.clone(), *op);
+        self.position += 2;
+
+        let right = self.parse_assignment()?;
+        let value = match op.strip_suffix('=').filter(|op| !op.is_empty()) {
+            Some(op) => {
+                let left = self.value(&name)?;
+                self.binary(op, left, right)?
+            }
+            None => right,
+        };
+        self.assign(&name, value);
+        Ok(value)
+    }
+
+    fn parse_ternary(&mut self) -> Result<i64> {
+        let condition = self.parse_binary(0)?;
+        if !self.peek_operator("?") {
+            return Ok(condition);
+        }
+        self.position += 1;
+        let then = self.maybe_skip(condition == 0, Self::parse_comma)?;
+        self.expect_operator(":")?;
+        let otherwise = self.maybe_skip(condition != 0, Self::parse_ternary)?;
+        Ok(if condition != 0 { then } else { otherwise })
+    }
+
+    /// Parses binary operators that bind at least as tightly as `PRECEDENCE[level]`
+    fn parse_binary(&mut self, level: usize) -> Result<i64> {
+        if level == PRECEDENCE.len() {
+            return self.parse_unary();
+        }
+        let mut left = self.parse_binary(level + 1)?;
+        while let Some(Token::Operator(op)) = self.peek_at(0)
+            && PRECEDENCE[level].contains(op)
+        {
+            let op = *op;
+            self.position += 1;
+            left = match op {
+                // The right side only matters when the left doesn't already decide it
+                "&&" => {
+                    let right = self.maybe_skip(left == 0, |this| this.parse_binary(level + 1))?;
+                    (left != 0 && right != 0) as i64
+                }
+                "||" => {
+                    let right = self.maybe_skip(left != 0, |this| this.parse_binary(level + 1))?;
+                    (left != 0 || right != 0) as i64
+                }
+                // `**` groups from the right, so `2**3**2` is `2**9`
+                "**" => {
+                    let right = self.parse_binary(level)?;
+                    return self.binary(op, left, right);
+                }
+                _ => {
+                    let right = self.parse_binary(level + 1)?;
+                    self.binary(op, left, right)?
+                }
+            };
+        }
+        Ok(left)
+    }
+
+    fn binary(&self, op: &str, left: i64, right: i64) -> Result<i64> {
+        Ok(match op {
+            "|" => left | right,
+            "^" => left ^ right,
+            "&" => left & right,
+            "==" => (left == right) as i64,
+            "!=" => (left != right) as i64,
+            "<" => (left < right) as i64,
+            ">" => (left > right) as i64,
+            "<=" => (left <= right) as i64,
+            ">=" => (left >= right) as i64,
+            "<<" => left.wrapping_shl(right as u32),
+            ">>" => left.wrapping_shr(right as u32),
+            "+" => left.wrapping_add(right),
+            "-" => left.wrapping_sub(right),
+            "*" => left.wrapping_mul(right),
+            "/" | "%" if right == 0 => match self.skipping {
+                true => 0,
+                false => return Err(self.error("division by 0")),
+            },
+            "/" => left.wrapping_div(right),
+            "%" => left.wrapping_rem(right),
+            "**" if right < 0 => return Err(self.error("exponent less than 0")),
+            "**" => left.wrapping_pow(right.try_into().unwrap_or(u32::MAX)),
+            _ => unreachable!("{op} is in PRECEDENCE"),
+        })
+    }
+
+    fn parse_unary(&mut self) -> Result<i64> {
+        let Some(Token::Operator(op)) = self.peek_at(0) else {
+            return self.parse_postfix();
+        };
+        let op = *op;
+        match op {
+            "!" | "~" | "-" | "+" => {
+                self.position += 1;
+                let value = self.parse_unary()?;
+                Ok(match op {
+                    "!" => (value == 0) as i64,
+                    "~" => !value,
+                    "-" => value.wrapping_neg(),
+                    _ => value,
+                })
+            }
+            "++" | "--" => {
+                self.position += 1;
+                let Some(Token::Name(name)) = self.peek_at(0).cloned() else {
+                    return Err(self.syntax_error());
+                };
+                self.position += 1;
+                let value = self.value(&name)?;
+                let value = match op {
+                    "++" => value.wrapping_add(1),
+                    _ => value.wrapping_sub(1),
+                };
+                self.assign(&name, value);
+                Ok(value)
+            }
+            _ => self.parse_postfix(),
+        }
+    }
+
+    fn parse_postfix(&mut self) -> Result<i64> {
+        match self.peek_at(0).cloned() {
+            Some(Token::Number(value)) => {
+                self.position += 1;
+                Ok(value)
+            }
+            Some(Token::Name(name)) => {
+                self.position += 1;
+                let value = self.value(&name)?;
+                if self.peek_operator("++") || self.peek_operator("--") {
+                    let changed = match self.peek_operator("++") {
+                        true => value.wrapping_add(1),
+                        false => value.wrapping_sub(1),
+                    };
+                    self.position += 1;
+                    self.assign(&name, changed);
+                }
+                Ok(value)
+            }
+            Some(Token::Operator("(")) => {
+                self.position += 1;
+                let value = self.parse_comma()?;
+                self.expect_operator(")")?;
+                Ok(value)
+            }
+            _ => Err(self.syntax_error()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::builtins::history::History;
+
+    fn eval(expression: &str) -> Result<i64> {
+        evaluate(&mut Shell::new(History::new()), expression)
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(eval("1 + 2 * 3").unwrap(), 7);
+        assert_eq!(eval("(1 + 2) * 3").unwrap(), 9);
+        assert_eq!(eval("10 - 4 - 3").unwrap(), 3);
+        assert_eq!(eval("1 << 2 + 1").unwrap(), 8);
+        assert_eq!(eval("1 + 1 == 2 && 3 > 2").unwrap(), 1);
+        assert_eq!(eval("-2 ** 2").unwrap(), 4);
+        assert_eq!(eval("0 ? 1 : 2 ? 3 : 4").unwrap(), 3);
+    }
+
+    #[test]
+    fn power_is_right_associative() {
+        assert_eq!(eval("2 ** 3 ** 2").unwrap(), 512);
+        assert_eq!(eval("2 * 3 ** 2").unwrap(), 18);
+    }
+
+    #[test]
+    fn bases() {
+        assert_eq!(eval("0x1f").unwrap(), 31);
+        assert_eq!(eval("0X10").unwrap(), 16);
+        assert_eq!(eval("010").unwrap(), 8);
+        assert_eq!(eval("2#101").unwrap(), 5);
+        assert_eq!(eval("36#z").unwrap(), 35);
+        assert!(eval("08").is_err());
+        assert!(eval("2#2").is_err());
+        assert!(eval("65#1").is_err());
+    }
+
+    #[test]
+    fn division_by_zero() {
+        assert!(eval("1 / 0").is_err());
+        assert!(eval("1 % 0").is_err());
+        assert_eq!(eval("7 / 2").unwrap(), 3);
+        assert_eq!(eval("-7 % 3").unwrap(), -1);
+        // Short-circuiting skips the division entirely
+        assert_eq!(eval("0 && 1 / 0").unwrap(), 0);
+    }
+
+    #[test]
+    fn self_reference() {
+        let mut shell = Shell::new(History::new());
+        shell.variables.set("a", "b + 1".to_string());
+        shell.variables.set("b", "2".to_string());
+        assert_eq!(evaluate(&mut shell, "a * 2").unwrap(), 6);
+
+        shell.variables.set("x", "x".to_string());
+        let e = evaluate(&mut shell, "x").unwrap_err();
+        assert!(
+            e.to_string()
+                .contains("expression recursion level exceeded")
+        );
+
+        // A builtin in a pipeline runs on its own thread, with a smaller stack
+        shell.variables.set("y", "z + 1".to_string());
+        shell.variables.set("z", "y + 1".to_string());
+        let result = thread::spawn(move || evaluate(&mut shell, "y").is_err());
+        assert!(result.join().unwrap());
+    }
+
+    #[test]
+    fn assignment() {
+        let mut shell = Shell::new(History::new());
+        assert_eq!(evaluate(&mut shell, "x = 5, x += 2, x++").unwrap(), 7);
+        assert_eq!(shell.variables.get("x"), Some("8"));
+        assert_eq!(evaluate(&mut shell, "--x * 2").unwrap(), 14);
+    }
+}
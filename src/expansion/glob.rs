@@ -0,0 +1,153 @@
+use std::fs;
+use std::path::Path;
+
+use crate::expansion::pattern::{is_bracket_closed, pattern_matches};
+use crate::shell::options::Options;
+
+/// Whether a pattern has any unescaped wildcards in it, so that it needs to be matched against
+/// the filesystem
+pub fn has_wildcards(pattern: &str) -> bool {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            '*' | '?' => return true,
+            '[' if is_bracket_closed(&chars, i) => return true,
+            _ => {}
+        }
+        i += 1;
+    }
+    false
+}
+
+fn unescape(pattern: &str) -> String {
+    let mut text = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => text.extend(chars.next()),
+            _ => text.push(c),
+        }
+    }
+    text
+}
+
+/// Finds every path matching a pattern, sorted. Each `/` separated component is matched against
+/// the entries of the directories the previous components matched.
+pub fn glob(pattern: &str, options: &Options) -> Vec<String> {
+    let (mut prefixes, rest) = match pattern.strip_prefix('/') {
+        Some(rest) => (vec!["/".to_string()], rest),
+        None => (vec![String::new()], pattern),
+    };
+    let components: Vec<&str> = rest.split('/').collect();
+
+    for (i, component) in components.iter().enumerate() {
+        let last = i == components.len() - 1;
+        let mut matches = vec![];
+
+        for prefix in &prefixes {
+            if component.is_empty() {
+                // A trailing or doubled slash, which only directories can have
+                matches.push(prefix.clone());
+            } else if !has_wildcards(component) {
+                let path = format!("{prefix}{}", unescape(component));
+                if last {
+                    matches.push(path);
+                } else {
+                    matches.push(format!("{path}/"));
+                }
+            } else if *component == "**" && options.globstar {
+                let mut found = vec![];
+                if !last {
+                    found.push(prefix.clone());
+                }
+                walk(prefix, last, options, &mut found);
+                matches.extend(found);
+            } else {
+                for name in dir_entries(prefix) {
+                    let hidden = name.starts_with('.');
+                    if hidden && !(component.starts_with('.') || options.dotglob) {
+                        continue;
+                    }
+                    if !pattern_matches(component, &name) {
+                        continue;
+                    }
+                    let path = format!("{prefix}{name}");
+                    if last {
+                        matches.push(path);
+                    } else if Path::new(&path).is_dir() {
+                        matches.push(format!("{path}/"));
+                    }
+                }
+            }
+        }
+        prefixes = matches;
+    }
+
+    let mut matches: Vec<String> = prefixes
+        .into_iter()
+        .filter(|path| Path::new(path).symlink_metadata().is_ok())
+        .collect();
+    matches.sort();
+    matches.dedup();
+    matches
+}
+
+/// The names in a directory, where an empty prefix means the current directory
+fn dir_entries(prefix: &str) -> Vec<String> {
+    let dir = if prefix.is_empty() { "." } else { prefix };
+    let Ok(entries) = fs::read_dir(dir) else {
+        return vec![];
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect()
+}
+
+/// Collects everything under `prefix` for `**`. When `**` is the last component it matches files
+/// too, otherwise only directories are kept so the next component can look inside of them.
+fn walk(prefix: &str, include_files: bool, options: &Options, found: &mut Vec<String>) {
+    for name in dir_entries(prefix) {
+        if name.starts_with('.') && !options.dotglob {
+            continue;
+        }
+        let path = format!("{prefix}{name}");
+        let metadata = Path::new(&path).symlink_metadata();
+        if metadata.as_ref().is_ok_and(|metadata| metadata.is_dir()) {
+            if include_files {
+                found.push(path.clone());
+            }
+            let dir = format!("{path}/");
+            if !include_files {
+                found.push(dir.clone());
+            }
+            walk(&dir, include_files, options, found);
+        } else if include_files {
+            found.push(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcards() {
+        assert!(has_wildcards("*.rs"));
+        assert!(has_wildcards("a?"));
+        assert!(has_wildcards("[ab]"));
+        assert!(!has_wildcards("plain"));
+        assert!(!has_wildcards("\\*"));
+    }
+
+    #[test]
+    fn unclosed_bracket_is_literal() {
+        assert!(!has_wildcards("["));
+        assert!(!has_wildcards("a[b"));
+        assert!(!has_wildcards("\\[ab]"));
+        assert!(has_wildcards("[[:digit:]]"));
+    }
+}
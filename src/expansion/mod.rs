@@ -0,0 +1,5 @@
+pub mod arithmetic;
+pub mod glob;
+pub mod parameter;
+pub mod pattern;
+pub mod utils;
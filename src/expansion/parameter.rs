@@ -0,0 +1,167 @@
+use anyhow::{Result, anyhow};
+use thiserror::Error;
+
+use crate::expansion::pattern::pattern_matches;
+use crate::expansion::utils::{expand_pattern, expand_word_to_string};
+use crate::parser::ast::{Parameter, ParameterOp, Word};
+use crate::parser::lexer::{is_variable_name, split_subscript};
+use crate::shell::state::Shell;
+
+/// `${name:?message}` on a parameter that isn't set, which makes a shell that isn't interactive
+/// exit
+#[derive(Error, Debug)]
+#[error("{name}: {message}")]
+pub struct UnsetParameterError {
+    pub name: String,
+    pub message: String,
+}
+
+pub enum ParameterExpansion<'a> {
+    Value(String),
+    /// A word from the expansion, like the default in `${VAR:-word}`, which is expanded in the
+    /// same quoting context as the parameter
+    Word(&'a Word),
+}
+
+/// Looks up a parameter, which can be a variable or one of the special parameters like `$?`
+pub fn parameter_value(shell: &Shell, name: &str) -> Option<String> {
+    match name {
+        "?" => Some(shell.last_status.to_string()),
+        "$" => Some(shell.pid.to_string()),
+        "0" => Some(shell.script_name.clone()),
+        "#" => Some(shell.positional.len().to_string()),
+        "@" => Some(shell.positional.join(" ")),
+        // Joined with the first character of `$IFS`, which is what `"$*"` gives
+        "*" => {
+            let separator = match shell.variables.get("IFS") {
+                Some(ifs) => ifs.chars().next().map(String::from).unwrap_or_default(),
+                None => " ".to_string(),
+            };
+            Some(shell.positional.join(&separator))
+        }
+        "-" => Some(String::new()),
+        "!" => shell.background_pid.map(|pid| pid.to_string()),
+        _ if name.starts_with(|c: char| c.is_ascii_digit()) => {
+            let index = name.parse::<usize>().ok()?;
+            shell.positional.get(index.checked_sub(1)?).cloned()
+        }
+        _ => match split_subscript(name) {
+            (name, Some("@" | "*")) => Some(shell.variables.elements(name).join(" ")),
+            (name, Some(index)) => {
+                let index = index.trim().parse::<usize>().ok()?;
+                shell
+                    .variables
+                    .elements(name)
+                    .get(index)
+                    .map(|s| s.to_string())
+            }
+            (name, None) => shell.variables.get(name).map(str::to_string),
+        },
+    }
+}
+
+pub fn expand_parameter<'a>(
+    shell: &mut Shell,
+    parameter: &'a Parameter,
+) -> Result<ParameterExpansion<'a>> {
+    let name = parameter.name.as_str();
+    let value = parameter_value(shell, name);
+    // With a colon, an empty value counts as unset
+    let is_set = |colon: bool| match &value {
+        Some(value) => !(colon && value.is_empty()),
+        None => false,
+    };
+
+    let expansion = match &parameter.op {
+        ParameterOp::None => ParameterExpansion::Value(value.unwrap_or_default()),
+        // The number of elements in an array, like `${#BASH_REMATCH[@]}`
+        ParameterOp::Length if matches!(split_subscript(name).1, Some("@" | "*")) => {
+            let length = shell.variables.elements(split_subscript(name).0).len();
+            ParameterExpansion::Value(length.to_string())
+        }
+        ParameterOp::Length => {
+            let length = value.unwrap_or_default().chars().count();
+            ParameterExpansion::Value(length.to_string())
+        }
+        ParameterOp::Default { colon, word } => {
+            if is_set(*colon) {
+                ParameterExpansion::Value(value.unwrap_or_default())
+            } else {
+                ParameterExpansion::Word(word)
+            }
+        }
+        ParameterOp::Assign { colon, word } => {
+            if is_set(*colon) {
+                ParameterExpansion::Value(value.unwrap_or_default())
+            } else {
+                if !is_variable_name(name) {
+                    return Err(anyhow!("${name}: cannot assign in this way"));
+                }
+                let assigned = expand_word_to_string(shell, word)?;
+                shell.variables.set(name, assigned.clone());
+                ParameterExpansion::Value(assigned)
+            }
+        }
+        ParameterOp::Error { colon, word } => {
+            if is_set(*colon) {
+                ParameterExpansion::Value(value.unwrap_or_default())
+            } else {
+                let mut message = expand_word_to_string(shell, word)?;
+                if message.is_empty() {
+                    message = "parameter null or not set".to_string();
+                }
+                let name = name.to_string();
+                return Err(UnsetParameterError { name, message }.into());
+            }
+        }
+        ParameterOp::Alternative { colon, word } => {
+            if is_set(*colon) {
+                ParameterExpansion::Word(word)
+            } else {
+                ParameterExpansion::Value(String::new())
+            }
+        }
+        ParameterOp::RemovePrefix { longest, pattern } => {
+            let pattern = expand_pattern(shell, pattern)?;
+            let value = value.unwrap_or_default();
+            ParameterExpansion::Value(remove_prefix(&value, &pattern, *longest))
+        }
+        ParameterOp::RemoveSuffix { longest, pattern } => {
+            let pattern = expand_pattern(shell, pattern)?;
+            let value = value.unwrap_or_default();
+            ParameterExpansion::Value(remove_suffix(&value, &pattern, *longest))
+        }
+    };
+    Ok(expansion)
+}
+
+/// Every place a value can be cut at, from the start of it to the end
+fn boundaries(value: &str) -> Vec<usize> {
+    value
+        .char_indices()
+        .map(|(i, _)| i)
+        .chain([value.len()])
+        .collect()
+}
+
+fn remove_prefix(value: &str, pattern: &str, longest: bool) -> String {
+    let mut cuts = boundaries(value);
+    if longest {
+        cuts.reverse();
+    }
+    cuts.into_iter()
+        .find(|&cut| pattern_matches(pattern, &value[..cut]))
+        .map(|cut| value[cut..].to_string())
+        .unwrap_or_else(|| value.to_string())
+}
+
+fn remove_suffix(value: &str, pattern: &str, longest: bool) -> String {
+    let mut cuts = boundaries(value);
+    if !longest {
+        cuts.reverse();
+    }
+    cuts.into_iter()
+        .find(|&cut| pattern_matches(pattern, &value[cut..]))
+        .map(|cut| value[..cut].to_string())
+        .unwrap_or_else(|| value.to_string())
+}
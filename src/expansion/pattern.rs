@@ -0,0 +1,189 @@
+/// Whether `text` matches a shell pattern, where `*`, `?` and `[...]` are wildcards and a
+/// backslash makes the next character literal
+pub fn pattern_matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    let mut p = 0;
+    let mut t = 0;
+    // Where the last `*` was, and how much of the text it has swallowed so far
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if pattern.get(p) == Some(&'*') {
+            star = Some((p, t));
+            p += 1;
+            continue;
+        }
+        if let Some(next_p) = match_one(&pattern, p, text[t]) {
+            p = next_p;
+            t += 1;
+            continue;
+        }
+        match star {
+            Some((star_p, star_t)) => {
+                star = Some((star_p, star_t + 1));
+                p = star_p + 1;
+                t = star_t + 1;
+            }
+            None => return false,
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Matches a single non-`*` element of the pattern against `c`, giving back where the next
+/// element starts if it matched
+fn match_one(pattern: &[char], p: usize, c: char) -> Option<usize> {
+    match pattern.get(p)? {
+        '?' => Some(p + 1),
+        '[' => match match_bracket(pattern, p, c) {
+            Some((true, next_p)) => Some(next_p),
+            Some((false, _)) => None,
+            // No closing bracket, so it's a literal `[`
+            None => (c == '[').then_some(p + 1),
+        },
+        '\\' if p + 1 < pattern.len() => (pattern[p + 1] == c).then_some(p + 2),
+        literal => (*literal == c).then_some(p + 1),
+    }
+}
+
+/// Matches a bracket expression starting at `pattern[start]`, giving back whether it matched and
+/// where the expression ends, or `None` if the bracket is never closed
+fn match_bracket(pattern: &[char], start: usize, c: char) -> Option<(bool, usize)> {
+    let mut i = start + 1;
+    let negated = matches!(pattern.get(i), Some('!') | Some('^'));
+    if negated {
+        i += 1;
+    }
+
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        let current = *pattern.get(i)?;
+        if current == ']' && !first {
+            return Some((matched != negated, i + 1));
+        }
+        first = false;
+
+        if current == '[' && pattern.get(i + 1) == Some(&':') {
+            let class_end = (i + 2..pattern.len().saturating_sub(1))
+                .find(|&j| pattern[j] == ':' && pattern[j + 1] == ']');
+            if let Some(class_end) = class_end {
+                let class: String = pattern[i + 2..class_end].iter().collect();
+                matched |= in_class(&class, c);
+                i = class_end + 2;
+                continue;
+            }
+        }
+
+        let low = if current == '\\' {
+            i += 1;
+            *pattern.get(i)?
+        } else {
+            current
+        };
+        if pattern.get(i + 1) == Some(&'-') && pattern.get(i + 2).is_some_and(|c| *c != ']') {
+            let high = *pattern.get(i + 2)?;
+            matched |= low <= c && c <= high;
+            i += 3;
+        } else {
+            matched |= low == c;
+            i += 1;
+        }
+    }
+}
+
+/// Whether the `[` at `pattern[start]` starts a bracket expression rather than being a literal
+/// `[`, which it only does when there's a `]` to close it
+pub fn is_bracket_closed(pattern: &[char], start: usize) -> bool {
+    match_bracket(pattern, start, '\0').is_some()
+}
+
+fn in_class(class: &str, c: char) -> bool {
+    match class {
+        "alpha" => c.is_alphabetic(),
+        "digit" => c.is_ascii_digit(),
+        "alnum" => c.is_alphanumeric(),
+        "upper" => c.is_uppercase(),
+        "lower" => c.is_lowercase(),
+        "space" => c.is_whitespace(),
+        "blank" => c == ' ' || c == '\t',
+        "punct" => c.is_ascii_punctuation(),
+        "xdigit" => c.is_ascii_hexdigit(),
+        "cntrl" => c.is_control(),
+        "print" => !c.is_control(),
+        "graph" => !c.is_control() && !c.is_whitespace(),
+        _ => false,
+    }
+}
+
+/// Escapes a string so that every character in it matches literally when used as a pattern
+pub fn escape_pattern(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcards() {
+        assert!(pattern_matches("*.rs", "main.rs"));
+        assert!(!pattern_matches("*.rs", "main.rc"));
+        assert!(pattern_matches("a?c", "abc"));
+        assert!(!pattern_matches("a?c", "ac"));
+        assert!(pattern_matches("a*b*c", "axxbyyc"));
+        assert!(pattern_matches("\\*", "*"));
+        assert!(!pattern_matches("\\*", "a"));
+    }
+
+    #[test]
+    fn bracket_expressions() {
+        assert!(pattern_matches("[abc]", "b"));
+        assert!(!pattern_matches("[abc]", "d"));
+        assert!(pattern_matches("[a-c]x", "cx"));
+        assert!(pattern_matches("[!a-c]", "d"));
+        assert!(pattern_matches("[^a-c]", "d"));
+        assert!(!pattern_matches("[!a-c]", "a"));
+        // A `]` straight after the `[` is part of the set, as is a `-` at the end
+        assert!(pattern_matches("[]]", "]"));
+        assert!(pattern_matches("[a-]", "-"));
+        assert!(pattern_matches("[\\]]", "]"));
+    }
+
+    #[test]
+    fn character_classes() {
+        assert!(pattern_matches("[[:digit:]]*", "42"));
+        assert!(!pattern_matches("[[:digit:]]", "a"));
+        assert!(pattern_matches("[[:upper:][:space:]]", " "));
+        assert!(pattern_matches("[![:alpha:]]", "1"));
+        assert!(!pattern_matches("[[:nonsense:]]", "a"));
+    }
+
+    #[test]
+    fn unclosed_bracket() {
+        assert!(pattern_matches("[", "["));
+        assert!(pattern_matches("a[b", "a[b"));
+        assert!(!pattern_matches("[ab", "a"));
+        let chars: Vec<char> = "[ab".chars().collect();
+        assert!(!is_bracket_closed(&chars, 0));
+        let chars: Vec<char> = "[ab]".chars().collect();
+        assert!(is_bracket_closed(&chars, 0));
+    }
+
+    #[test]
+    fn escaping() {
+        let text = "a*b?[c]\\";
+        assert!(pattern_matches(&escape_pattern(text), text));
+        assert!(!pattern_matches(&escape_pattern("a*"), "ab"));
+    }
+}
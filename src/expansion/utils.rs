@@ -0,0 +1,277 @@
+use std::{mem, slice};
+
+use anyhow::{Result, anyhow};
+
+use crate::expansion::arithmetic::evaluate;
+use crate::expansion::glob::{glob, has_wildcards};
+use crate::expansion::parameter::{ParameterExpansion, expand_parameter};
+use crate::expansion::pattern::escape_pattern;
+use crate::parser::ast::{Parameter, ParameterOp, Word, WordPart};
+use crate::shell::state::Shell;
+use crate::subprocesses::executor::capture_output;
+
+const DEFAULT_IFS: &str = " \t\n";
+
+/// Collects the fields a word expands into. Each character remembers whether it was quoted, which
+/// decides whether it can be split on or act as a wildcard.
+struct Fields {
+    fields: Vec<Vec<(char, bool)>>,
+    current: Vec<(char, bool)>,
+    started: bool,
+    ifs: Option<String>,
+}
+
+impl Fields {
+    fn new(ifs: Option<String>) -> Fields {
+        Fields {
+            fields: vec![],
+            current: vec![],
+            started: false,
+            ifs,
+        }
+    }
+
+    fn push_text(&mut self, text: &str, quoted: bool) {
+        self.started = true;
+        self.current.extend(text.chars().map(|c| (c, quoted)));
+    }
+
+    /// Pushes the result of an expansion, which gets split into several fields on `$IFS` unless
+    /// it was quoted
+    fn push_expansion(&mut self, text: &str, quoted: bool) {
+        let Some(ifs) = self.ifs.clone().filter(|_| !quoted) else {
+            self.push_text(text, quoted);
+            return;
+        };
+
+        for c in text.chars() {
+            if !ifs.contains(c) {
+                self.started = true;
+                self.current.push((c, false));
+            } else if !c.is_whitespace() || self.started {
+                self.started = true;
+                self.finish_field();
+            }
+        }
+    }
+
+    /// Ends the current field, even if there's nothing in it
+    fn split(&mut self) {
+        self.started = true;
+        self.finish_field();
+    }
+
+    fn finish_field(&mut self) {
+        if self.started {
+            self.fields.push(mem::take(&mut self.current));
+            self.started = false;
+        }
+    }
+
+    fn finish(mut self) -> Vec<Vec<(char, bool)>> {
+        self.finish_field();
+        self.fields
+    }
+}
+
+fn field_text(field: &[(char, bool)]) -> String {
+    field.iter().map(|(c, _)| c).collect()
+}
+
+fn field_pattern(field: &[(char, bool)]) -> String {
+    let mut pattern = String::new();
+    for (c, quoted) in field {
+        if *quoted {
+            pattern.push_str(&escape_pattern(&c.to_string()));
+        } else {
+            pattern.push(*c);
+        }
+    }
+    pattern
+}
+
+/// Expands a word into the fields it becomes once expansions have been split on `$IFS` and
+/// patterns have been matched against paths, with its quoting removed
+pub fn expand_word(shell: &mut Shell, word: &Word) -> Result<Vec<String>> {
+    let ifs = shell
+        .variables
+        .get("IFS")
+        .unwrap_or(DEFAULT_IFS)
+        .to_string();
+    let mut fields = Fields::new(Some(ifs));
+    expand_parts(shell, &word.parts, false, &mut fields)?;
+
+    let mut expanded = vec![];
+    for field in fields.finish() {
+        let pattern = field_pattern(&field);
+        if !has_wildcards(&pattern) {
+            expanded.push(field_text(&field));
+            continue;
+        }
+
+        let matches = glob(&pattern, &shell.options);
+        if !matches.is_empty() {
+            expanded.extend(matches);
+        } else if shell.options.failglob {
+            return Err(anyhow!("no match: {}", field_text(&field)));
+        } else if !shell.options.nullglob {
+            expanded.push(field_text(&field));
+        }
+    }
+    Ok(expanded)
+}
+
+/// Expands a word into a single string without splitting it, like the target of a redirect
+pub fn expand_word_to_string(shell: &mut Shell, word: &Word) -> Result<String> {
+    let mut fields = Fields::new(None);
+    expand_parts(shell, &word.parts, false, &mut fields)?;
+    Ok(fields
+        .finish()
+        .iter()
+        .map(|field| field_text(field))
+        .collect())
+}
+
+/// Expands a word that's going to be used as a pattern, escaping anything that was quoted so it
+/// only matches literally
+pub fn expand_pattern(shell: &mut Shell, word: &Word) -> Result<String> {
+    let mut fields = Fields::new(None);
+    expand_parts(shell, &word.parts, false, &mut fields)?;
+    Ok(fields
+        .finish()
+        .iter()
+        .map(|field| field_pattern(field))
+        .collect())
+}
+
+/// Expands a word that's going to be used as a regex, like the right of `=~` in `[[ ]]`, escaping
+/// anything that was quoted so it only matches literally
+pub fn expand_regex(shell: &mut Shell, word: &Word) -> Result<String> {
+    let mut fields = Fields::new(None);
+    expand_parts(shell, &word.parts, false, &mut fields)?;
+    let mut regex = String::new();
+    for (c, quoted) in fields.finish().concat() {
+        if quoted {
+            regex.push_str(&regex::escape(&c.to_string()));
+        } else {
+            regex.push(c);
+        }
+    }
+    Ok(regex)
+}
+
+/// Whether a parameter is a plain `$@`, which expands to each positional parameter as a separate
+/// field even when it's quoted
+fn is_all_positional(parameter: &Parameter) -> bool {
+    parameter.name == "@" && parameter.op == ParameterOp::None
+}
+
+/// Pushes `$@`, starting a new field for each positional parameter. Where the word isn't split,
+/// like `x="$@"`, they're joined with spaces instead.
+fn push_all_positional(shell: &Shell, quoted: bool, fields: &mut Fields) {
+    for (index, arg) in shell.positional.iter().enumerate() {
+        if index > 0 {
+            match fields.ifs {
+                Some(_) if quoted => fields.split(),
+                Some(_) => fields.finish_field(),
+                None => fields.push_text(" ", quoted),
+            }
+        }
+        fields.push_expansion(arg, quoted);
+    }
+}
+
+/// Runs a command and gives back what it printed, without any trailing newlines
+fn command_substitution(shell: &mut Shell, command: &str) -> Result<String> {
+    let list = shell.parse(command)?;
+    let (output, status) = capture_output(&list, shell)?;
+    shell.last_status = status;
+    shell.substitution_status = Some(status);
+
+    let output = String::from_utf8_lossy(&output);
+    Ok(output.trim_end_matches('\n').to_string())
+}
+
+fn expand_parts(
+    shell: &mut Shell,
+    parts: &[WordPart],
+    quoted: bool,
+    fields: &mut Fields,
+) -> Result<()> {
+    for part in parts {
+        match part {
+            WordPart::Literal(literal) => fields.push_text(literal, quoted),
+            WordPart::Quoted(literal) => fields.push_text(literal, true),
+            WordPart::DoubleQuoted(parts) => {
+                // `""` still makes a field, even though there's nothing in it, but `"$@"` without
+                // any positional parameters doesn't
+                let only_all_positional = matches!(
+                    parts.as_slice(),
+                    [WordPart::Parameter(parameter)] if is_all_positional(parameter)
+                );
+                if !only_all_positional {
+                    fields.push_text("", true);
+                }
+                expand_parts(shell, parts, true, fields)?;
+            }
+            WordPart::Parameter(parameter) if is_all_positional(parameter) => {
+                push_all_positional(shell, quoted, fields)
+            }
+            WordPart::Parameter(parameter) => match expand_parameter(shell, parameter)? {
+                ParameterExpansion::Value(value) => fields.push_expansion(&value, quoted),
+                ParameterExpansion::Word(word) => {
+                    for part in &word.parts {
+                        match part {
+                            // Unquoted, the text in something like `${x:-a b}` is split too
+                            WordPart::Literal(text) if !quoted => {
+                                fields.push_expansion(text, false)
+                            }
+                            part => expand_parts(shell, slice::from_ref(part), quoted, fields)?,
+                        }
+                    }
+                }
+            },
+            WordPart::CommandSubstitution(command) => {
+                let output = command_substitution(shell, command)?;
+                fields.push_expansion(&output, quoted);
+            }
+            WordPart::Arithmetic(expression) => {
+                let expression = expand_word_to_string(shell, expression)?;
+                let value = evaluate(shell, &expression)?;
+                fields.push_expansion(&value.to_string(), quoted);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builtins::history::History;
+    use crate::parser::lexer::{Token, tokenize};
+
+    fn expand(shell: &mut Shell, input: &str) -> Vec<String> {
+        match tokenize(input).unwrap().as_slice() {
+            [Token::Word(word)] => expand_word(shell, word).unwrap(),
+            tokens => panic!("expected one word, got {tokens:?}"),
+        }
+    }
+
+    #[test]
+    fn parameter_words_are_split_unless_quoted() {
+        let mut shell = Shell::new(History::new());
+        shell.variables.set("set_in_test", "v".to_string());
+        assert_eq!(expand(&mut shell, "${unset_in_test:-a b}"), ["a", "b"]);
+        assert_eq!(expand(&mut shell, "${set_in_test:+c  d}"), ["c", "d"]);
+        assert_eq!(
+            expand(&mut shell, "${unset_in_test:-1 2 3}x"),
+            ["1", "2", "3x"]
+        );
+        assert_eq!(expand(&mut shell, "\"${unset_in_test:-a b}\""), ["a b"]);
+        assert_eq!(
+            expand(&mut shell, "${unset_in_test:-'a b' c}"),
+            ["a b", "c"]
+        );
+    }
+}
@@ -1,4 +1,3 @@
-use std::env;
 use std::fs::read_dir;
 use std::io;
 use std::io::Write;
@@ -9,23 +8,22 @@ use anyhow::Result;
 use crossterm::event::{Event, KeyCode, read};
 use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
 
-use crate::builtins::history::History;
+use crate::builtins::BUILTINS;
+use crate::input::line::Line;
 use crate::input::utils::{InputLoop, handle_key_press};
+use crate::shell::state::Shell;
 
-const BUILTINS: [&str; 6] = ["echo", "exit", "type", "cd", "pwd", "history"];
-
-fn push_completed(completed: &str, current_input: &mut String) {
-    let to_push = completed.replace(current_input.as_str(), "");
-    for char in to_push.chars() {
-        current_input.push(char);
-        print!("{char}");
-    }
-    current_input.push(' ');
-    print!(" ");
-    io::stdout().flush().expect("Could not flush autocomplete");
+fn push_completed(completed: &str, input: &mut Line) -> Result<()> {
+    let to_push = &completed[input.before_cursor().len()..];
+    input.insert(to_push);
+    input.insert(" ");
+    input.redraw()?;
+    Ok(())
 }
 
-pub fn autocomplete(current_input: &mut String, history: &mut History) -> Result<InputLoop> {
+/// Completes the command name that's been typed up to the cursor
+pub fn autocomplete(input: &mut Line, shell: &mut Shell) -> Result<InputLoop> {
+    let current_input = input.before_cursor().to_string();
     let mut potential_matches: Vec<String> = vec![];
 
     // First check builtins
@@ -37,8 +35,15 @@ pub fn autocomplete(current_input: &mut String, history: &mut History) -> Result
         }
     }
 
+    // Then aliases and functions
+    for name in shell.aliases.keys().chain(shell.functions.keys()) {
+        if name.starts_with(current_input.as_str()) && !potential_matches.contains(name) {
+            potential_matches.push(name.clone());
+        }
+    }
+
     // Then search path
-    let path = env::var("PATH").unwrap();
+    let path = shell.variables.get("PATH").unwrap_or_default();
     let dirs = path.split(":");
     for dir in dirs {
         let dir_path = Path::new(dir);
@@ -58,7 +63,7 @@ pub fn autocomplete(current_input: &mut String, history: &mut History) -> Result
     }
 
     if potential_matches.len() == 1 {
-        push_completed(potential_matches.first().unwrap(), current_input);
+        push_completed(potential_matches.first().unwrap(), input)?;
     } else if potential_matches.len() > 1 {
         print!("\x07");
         io::stdout().flush().expect("Could not flush bell");
@@ -66,11 +71,8 @@ pub fn autocomplete(current_input: &mut String, history: &mut History) -> Result
         let longest_common_prefix =
             find_longest_common_prefix(&potential_matches, current_input.len());
         if !longest_common_prefix.is_empty() {
-            current_input.push_str(&longest_common_prefix);
-            print!("{longest_common_prefix}");
-            io::stdout()
-                .flush()
-                .expect("Could not flush longest_common_prefix");
+            input.insert(&longest_common_prefix);
+            input.redraw()?;
         }
 
         if let Ok(Event::Key(key_event)) = read() {
@@ -80,13 +82,10 @@ pub fn autocomplete(current_input: &mut String, history: &mut History) -> Result
                 disable_raw_mode()?;
                 println!();
                 println!("{potential_commands}");
-                print!("$ {current_input}");
-                io::stdout()
-                    .flush()
-                    .expect("Could not flush potential commands");
+                input.redraw()?;
                 enable_raw_mode()?;
             } else {
-                return handle_key_press(current_input, key_event, history);
+                return handle_key_press(input, key_event, shell);
             }
         }
     } else {
@@ -0,0 +1,292 @@
+use std::io::{self, Write};
+
+use crossterm::cursor::{MoveTo, MoveToColumn};
+use crossterm::execute;
+use crossterm::terminal::{Clear, ClearType};
+
+use crate::input::vi::Vi;
+
+/// The last thing a key did to the line, which decides what the next kill or yank does
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Action {
+    Kill,
+    /// Pasted kill ring entry `index` starting at byte `start`, up to the cursor
+    Yank {
+        start: usize,
+        index: usize,
+    },
+}
+
+/// Whether a character is part of a word for Alt-B, Alt-F and Alt-D
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric()
+}
+
+/// Whether a character is part of a word for Ctrl-W, which only stops at whitespace
+fn is_unix_word_char(c: char) -> bool {
+    !c.is_whitespace()
+}
+
+/// What's been typed at the prompt and where the cursor is in it. Earlier lines of a multi-line
+/// command are kept at the start, each ending in `\n`, but only the last line can be edited.
+#[derive(Default)]
+pub struct Line {
+    text: String,
+    /// A byte offset into `text`, which never goes back before the start of the last line
+    cursor: usize,
+    /// Text that's been killed, most recent last, which outlives the line it came from
+    kill_ring: Vec<String>,
+    /// What the key being handled did, and what the one before it did
+    action: Option<Action>,
+    previous_action: Option<Action>,
+    /// The state of vi mode, which is only used when it's turned on
+    pub vi: Vi,
+}
+
+impl Line {
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    /// Where the line being edited starts
+    pub fn line_start(&self) -> usize {
+        self.text.rfind('\n').map_or(0, |i| i + 1)
+    }
+
+    /// The line being edited
+    pub fn current(&self) -> &str {
+        &self.text[self.line_start()..]
+    }
+
+    /// The part of the line being edited that's before the cursor
+    pub fn before_cursor(&self) -> &str {
+        &self.text[self.line_start()..self.cursor]
+    }
+
+    pub fn clear(&mut self) {
+        self.text.clear();
+        self.cursor = 0;
+        self.vi.reset();
+    }
+
+    /// The whole text and the cursor, to be put back later with `restore`
+    pub fn snapshot(&self) -> (String, usize) {
+        (self.text.clone(), self.cursor)
+    }
+
+    pub fn restore(&mut self, (text, cursor): (String, usize)) {
+        self.text = text;
+        self.cursor = cursor;
+    }
+
+    /// How many characters into the line being edited the cursor is
+    pub fn cursor_index(&self) -> usize {
+        self.before_cursor().chars().count()
+    }
+
+    /// Moves the cursor to a character in the line being edited, or the end if it's past it
+    pub fn set_cursor_index(&mut self, index: usize) {
+        let offset = self
+            .current()
+            .char_indices()
+            .nth(index)
+            .map_or(self.current().len(), |(offset, _)| offset);
+        self.cursor = self.line_start() + offset;
+    }
+
+    /// Replaces characters `start..end` of the line being edited, leaving the cursor on `start`
+    pub fn replace(&mut self, start: usize, end: usize, text: &str) {
+        self.set_cursor_index(end);
+        let end = self.cursor;
+        self.set_cursor_index(start);
+        self.text.replace_range(self.cursor..end, text);
+    }
+
+    /// Finishes the line being edited and starts a new one, for a command that needs more lines
+    pub fn new_line(&mut self) {
+        self.text.push('\n');
+        self.cursor = self.text.len();
+    }
+
+    /// Replaces the line being edited, like when recalling history, leaving the cursor at the end
+    pub fn set_current(&mut self, text: &str) {
+        self.text.truncate(self.line_start());
+        self.text.push_str(text);
+        self.cursor = self.text.len();
+    }
+
+    pub fn insert(&mut self, text: &str) {
+        self.text.insert_str(self.cursor, text);
+        self.cursor += text.len();
+    }
+
+    /// Removes the character before the cursor, if it's on the line being edited
+    pub fn delete_before(&mut self) -> Option<char> {
+        if self.cursor == self.line_start() {
+            return None;
+        }
+        self.move_left();
+        Some(self.text.remove(self.cursor))
+    }
+
+    /// Removes the character under the cursor
+    pub fn delete_at(&mut self) -> Option<char> {
+        (self.cursor < self.text.len()).then(|| self.text.remove(self.cursor))
+    }
+
+    pub fn move_left(&mut self) {
+        if let Some(c) = self.before_cursor().chars().next_back() {
+            self.cursor -= c.len_utf8();
+        }
+    }
+
+    pub fn move_right(&mut self) {
+        if let Some(c) = self.text[self.cursor..].chars().next() {
+            self.cursor += c.len_utf8();
+        }
+    }
+
+    pub fn move_home(&mut self) {
+        self.cursor = self.line_start();
+    }
+
+    pub fn move_end(&mut self) {
+        self.cursor = self.text.len();
+    }
+
+    /// Starts handling a key, so that a kill or yank can tell if it's following on from another
+    pub fn start_key(&mut self) {
+        self.previous_action = self.action.take();
+    }
+
+    /// Where the word the cursor is in or after starts, going back over anything in between
+    fn word_start(&self, is_word: fn(char) -> bool) -> usize {
+        let before = self.before_cursor();
+        let trimmed = before.trim_end_matches(|c| !is_word(c));
+        let word = trimmed.trim_end_matches(is_word);
+        self.line_start() + word.len()
+    }
+
+    /// Where the word the cursor is in or before ends, going forward over anything in between
+    fn word_end(&self, is_word: fn(char) -> bool) -> usize {
+        let after = &self.text[self.cursor..];
+        let trimmed = after.trim_start_matches(|c| !is_word(c));
+        let rest = trimmed.trim_start_matches(is_word);
+        self.text.len() - rest.len()
+    }
+
+    pub fn move_word_left(&mut self) {
+        self.cursor = self.word_start(is_word_char);
+    }
+
+    pub fn move_word_right(&mut self) {
+        self.cursor = self.word_end(is_word_char);
+    }
+
+    /// Cuts out `start..end` into the kill ring, joining it onto the last entry if the key before
+    /// this one killed too, so Ctrl-Y brings back everything killed in a row
+    fn kill(&mut self, start: usize, end: usize) {
+        if start == end {
+            return;
+        }
+        let killed: String = self.text.drain(start..end).collect();
+        let joined = self.previous_action == Some(Action::Kill);
+        match self.kill_ring.last_mut() {
+            Some(last) if joined && start < self.cursor => last.insert_str(0, &killed),
+            Some(last) if joined => last.push_str(&killed),
+            _ => self.kill_ring.push(killed),
+        }
+        self.cursor = start;
+        self.action = Some(Action::Kill);
+    }
+
+    /// Ctrl-W: kills back to the start of the whitespace separated word before the cursor
+    pub fn kill_word_before(&mut self) {
+        self.kill(self.word_start(is_unix_word_char), self.cursor);
+    }
+
+    /// Alt-D: kills forward to the end of the word after the cursor
+    pub fn kill_word_after(&mut self) {
+        self.kill(self.cursor, self.word_end(is_word_char));
+    }
+
+    /// Ctrl-U: kills everything before the cursor
+    pub fn kill_to_start(&mut self) {
+        self.kill(self.line_start(), self.cursor);
+    }
+
+    /// Ctrl-K: kills everything from the cursor on
+    pub fn kill_to_end(&mut self) {
+        self.kill(self.cursor, self.text.len());
+    }
+
+    /// Ctrl-Y: pastes the most recent kill at the cursor
+    pub fn yank(&mut self) {
+        let Some(index) = self.kill_ring.len().checked_sub(1) else {
+            return;
+        };
+        let start = self.cursor;
+        self.insert(&self.kill_ring[index].clone());
+        self.action = Some(Action::Yank { start, index });
+    }
+
+    /// Alt-Y: straight after a yank, swaps what was pasted for the kill before it, going round to
+    /// the most recent again after the oldest
+    pub fn yank_pop(&mut self) {
+        let Some(Action::Yank { start, index }) = self.previous_action else {
+            return;
+        };
+        self.text.drain(start..self.cursor);
+        self.cursor = start;
+        let index = index.checked_sub(1).unwrap_or(self.kill_ring.len() - 1);
+        self.insert(&self.kill_ring[index].clone());
+        self.action = Some(Action::Yank { start, index });
+    }
+
+    /// Ctrl-T: swaps the character before the cursor with the one under it and moves on, or the
+    /// two before the cursor at the end of the line
+    pub fn transpose(&mut self) {
+        if self.cursor == self.line_start() {
+            return;
+        }
+        if self.cursor == self.text.len() {
+            self.move_left();
+            // There's only the one character
+            if self.cursor == self.line_start() {
+                self.move_end();
+                return;
+            }
+        }
+        let Some(c) = self.delete_before() else {
+            return;
+        };
+        self.move_right();
+        self.insert(c.encode_utf8(&mut [0; 4]));
+    }
+
+    /// Ctrl-L: clears the screen, leaving the prompt at the top
+    pub fn clear_screen(&self) -> io::Result<()> {
+        execute!(io::stdout(), Clear(ClearType::All), MoveTo(0, 0))?;
+        self.redraw()
+    }
+
+    /// `$ ` for the first line of a command and `> ` for the ones after it
+    pub fn prompt(&self) -> &'static str {
+        if self.line_start() == 0 { "$ " } else { "> " }
+    }
+
+    /// Draws the prompt and the line being edited over whatever was on the terminal's line, then
+    /// puts the terminal's cursor where ours is
+    pub fn redraw(&self) -> io::Result<()> {
+        let mut stdout = io::stdout();
+        execute!(stdout, MoveToColumn(0), Clear(ClearType::CurrentLine))?;
+        write!(stdout, "{}{}", self.prompt(), self.current())?;
+        let column = self.prompt().len() + self.before_cursor().chars().count();
+        execute!(stdout, MoveToColumn(column as u16))?;
+        stdout.flush()
+    }
+}
@@ -1,3 +1,6 @@
 pub mod autocomplete;
-pub mod inputblock;
+pub mod line;
+pub mod script;
+pub mod search;
 pub mod utils;
+pub mod vi;
@@ -0,0 +1,76 @@
+use std::io::{BufRead, Write};
+use std::mem;
+
+use anyhow::Result;
+
+use crate::shell::state::Shell;
+use crate::subprocesses::executor::{Outcome, execute_list};
+use crate::subprocesses::fds::Fds;
+
+/// Runs commands read from something other than the terminal, like a script, a `-c` string, a
+/// pipe or a file that's being `source`d. Lines are read one at a time and each command runs as
+/// soon as it's complete, so a script can `exit` partway through or leave the rest of a pipe for
+/// the commands it runs to read.
+///
+/// Errors start with `name` and the line they happened on. A syntax error stops the script like
+/// it would in other shells.
+pub fn run_script(
+    shell: &mut Shell,
+    reader: impl BufRead,
+    name: &str,
+    fds: &Fds,
+) -> Result<Outcome> {
+    // A sourced file goes back to reporting errors at the line that sourced it once it's done
+    let caller = mem::take(&mut shell.script_line);
+    let outcome = read_and_run(shell, reader, name, fds);
+    shell.script_line = caller;
+    outcome
+}
+
+fn read_and_run(
+    shell: &mut Shell,
+    mut reader: impl BufRead,
+    name: &str,
+    fds: &Fds,
+) -> Result<Outcome> {
+    let mut source = String::new();
+    let mut line_number = 0;
+    loop {
+        let mut line = String::new();
+        let at_end = reader.read_line(&mut line)? == 0;
+        if !at_end {
+            line_number += 1;
+        }
+        source.push_str(&line);
+        if source.trim().is_empty() {
+            source.clear();
+            if at_end {
+                break;
+            }
+            continue;
+        }
+
+        shell.script_line = Some((name.to_string(), line_number));
+        match shell.parse(source.trim()) {
+            Err(e) if e.is_incomplete() && !at_end => continue,
+            Err(e) => {
+                let _ = writeln!(fds.writer(2), "{}{e}", shell.error_prefix());
+                shell.last_status = 2;
+                return Ok(Outcome::Status(2));
+            }
+            Ok(list) => {
+                source.clear();
+                match execute_list(&list, shell, fds)? {
+                    Outcome::Exit(status) => return Ok(Outcome::Exit(status)),
+                    // Only a sourced file can be returned from
+                    Outcome::Return(status) => return Ok(Outcome::Status(status)),
+                    _ => {}
+                }
+            }
+        }
+        if at_end {
+            break;
+        }
+    }
+    Ok(Outcome::Status(shell.last_status))
+}
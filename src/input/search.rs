@@ -0,0 +1,119 @@
+use std::io::{self, Write};
+
+use anyhow::Result;
+use crossterm::cursor::MoveToColumn;
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers, read};
+use crossterm::execute;
+use crossterm::terminal::{Clear, ClearType};
+
+use crate::input::line::Line;
+use crate::input::utils::{InputLoop, handle_key_press};
+use crate::shell::state::Shell;
+
+/// Ctrl-R: searches back through history for entries containing what's typed, showing the
+/// closest match as it goes. Ctrl-R and Ctrl-S move on to older and newer matches, Enter runs
+/// the match, Ctrl-G puts the line back how it was, and Esc or an arrow key takes the
+/// match for editing.
+pub fn reverse_search(input: &mut Line, shell: &mut Shell) -> Result<InputLoop> {
+    let original = input.snapshot();
+    let mut query = String::new();
+    // The history entry being shown, which stays on the last match when one isn't found
+    let mut found: Option<usize> = None;
+    let mut failed = false;
+    let mut older = true;
+
+    loop {
+        let entry = found.and_then(|index| shell.history.get(index));
+        draw(&query, entry.map(String::as_str), failed, older)?;
+
+        let Ok(Event::Key(key_event)) = read() else {
+            continue;
+        };
+        match (key_event.code, key_event.modifiers) {
+            (KeyCode::Char('r'), KeyModifiers::CONTROL) => older = true,
+            (KeyCode::Char('s'), KeyModifiers::CONTROL) => older = false,
+            (KeyCode::Char(c), KeyModifiers::NONE | KeyModifiers::SHIFT) => {
+                query.push(c);
+                // The match being shown is kept for as long as it still contains the query
+                if entry.is_some_and(|entry| entry.contains(&query)) {
+                    continue;
+                }
+            }
+            (KeyCode::Backspace, _) => {
+                query.pop();
+                // Start again from the newest entry rather than remembering earlier matches
+                found = None;
+                failed = false;
+                older = true;
+            }
+            (KeyCode::Char('g'), KeyModifiers::CONTROL) => {
+                input.restore(original);
+                input.redraw()?;
+                return Ok(InputLoop::ContinueInner);
+            }
+            _ => return accept(input, shell, found, &query, key_event),
+        }
+
+        if query.is_empty() {
+            continue;
+        }
+        match shell
+            .history
+            .find(&query, found.unwrap_or(usize::MAX), older)
+        {
+            Some(index) => {
+                found = Some(index);
+                failed = false;
+            }
+            None => failed = true,
+        }
+    }
+}
+
+/// Draws `(reverse-i-search)`query': match` over the prompt, with the terminal's cursor on where
+/// the query is in the match
+fn draw(query: &str, entry: Option<&str>, failed: bool, older: bool) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    let failed = if failed { "failed " } else { "" };
+    let direction = if older { "reverse-" } else { "" };
+    let prompt = format!("({failed}{direction}i-search)`{query}': ");
+    // Earlier lines of a multi-line entry are shown on the same line
+    let entry = entry.unwrap_or_default().replace('\n', " ");
+    execute!(stdout, MoveToColumn(0), Clear(ClearType::CurrentLine))?;
+    write!(stdout, "{prompt}{entry}")?;
+    let offset = match query.is_empty() {
+        true => entry.len(),
+        false => entry.find(query).unwrap_or(entry.len()),
+    };
+    let column = prompt.chars().count() + entry[..offset].chars().count();
+    execute!(stdout, MoveToColumn(column as u16))?;
+    stdout.flush()
+}
+
+/// Puts the match on the line with the cursor where the query was found. Esc and the arrow keys
+/// just leave it there to be edited, while any other key that ends the search, like Enter, goes
+/// on to do what it normally does.
+fn accept(
+    input: &mut Line,
+    shell: &mut Shell,
+    found: Option<usize>,
+    query: &str,
+    key_event: KeyEvent,
+) -> Result<InputLoop> {
+    if let Some(index) = found
+        && let Some(entry) = shell.history.get(index).cloned()
+    {
+        input.set_current(&entry);
+        if let Some(offset) = entry.find(query) {
+            input.set_cursor_index(entry[..offset].chars().count());
+        }
+        shell.history.set_position(index);
+    }
+    input.redraw()?;
+    match key_event.code {
+        KeyCode::Esc | KeyCode::Left | KeyCode::Right | KeyCode::Up | KeyCode::Down => {
+            Ok(InputLoop::ContinueInner)
+        }
+        _ => handle_key_press(input, key_event, shell),
+    }
+}
@@ -1,41 +1,16 @@
-use std::fs::OpenOptions;
 use std::io::{self, Write};
 
 use anyhow::Result;
-use crossterm::cursor::{self, MoveToColumn};
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
-use crossterm::execute;
-use crossterm::terminal::{Clear, ClearType, disable_raw_mode};
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
 
-use crate::builtins::cd::cd_fn;
-use crate::builtins::history::{History, history_fn};
-use crate::builtins::pwd::pwd_fn;
-use crate::builtins::type_fn::type_fn;
 use crate::input::autocomplete::autocomplete;
-use crate::input::inputblock::InputBlock;
-use crate::subprocesses::utils::{OutputHandle, run_program};
-
-#[derive(Clone, PartialEq, Debug)]
-pub enum Redirect {
-    Stdout,
-    Stderr,
-    Pipe,
-    None,
-}
-
-#[derive(Clone, Debug)]
-pub enum RedirectType {
-    Create,
-    Append,
-    None,
-}
-
-#[derive(Clone, Debug)]
-pub struct RedirectOptions {
-    redirect: Redirect,
-    redirect_type: RedirectType,
-    redirect_location: String,
-}
+use crate::input::line::Line;
+use crate::input::search::reverse_search;
+use crate::input::vi::{handle_vi_key, set_cursor_shape};
+use crate::shell::state::Shell;
+use crate::subprocesses::executor::{Outcome, execute_list};
+use crate::subprocesses::fds::Fds;
 
 pub enum InputLoop {
     ContinueOuter,
@@ -44,286 +19,177 @@ pub enum InputLoop {
 }
 
 pub fn handle_key_press(
-    input: &mut String,
+    input: &mut Line,
     key_event: KeyEvent,
-    history: &mut History,
+    shell: &mut Shell,
 ) -> Result<InputLoop> {
+    input.start_key();
+    if shell.options.vi && handle_vi_key(input, key_event, shell)? {
+        return Ok(InputLoop::ContinueInner);
+    }
     match (key_event.code, key_event.modifiers) {
+        // Only entries starting with what's been typed are gone through
         (KeyCode::Up, _) => {
-            match history.move_up() {
-                Some(entry) => *input = entry.clone(),
-                None => *input = String::new(),
+            if let Some(entry) = shell.history.move_up(input.current()).cloned() {
+                input.set_current(&entry);
+                input.redraw()?;
             }
-
-            execute!(io::stdout(), MoveToColumn(0))?;
-            execute!(io::stdout(), Clear(ClearType::CurrentLine))?;
-            print!("$ {input}");
-            io::stdout().flush().expect("Could not history up");
         }
         (KeyCode::Down, _) => {
-            match history.move_down() {
-                Some(entry) => *input = entry.clone(),
-                None => *input = String::new(),
+            if let Some(entry) = shell.history.move_down().cloned() {
+                input.set_current(&entry);
+                input.redraw()?;
             }
-
-            execute!(io::stdout(), MoveToColumn(0))?;
-            execute!(io::stdout(), Clear(ClearType::CurrentLine))?;
-            print!("$ {input}");
-            io::stdout().flush().expect("Could not history down");
         }
+        (KeyCode::Left, _) => {
+            input.move_left();
+            input.redraw()?;
+        }
+        (KeyCode::Right, _) => {
+            input.move_right();
+            input.redraw()?;
+        }
+        (KeyCode::Home, _) | (KeyCode::Char('a'), KeyModifiers::CONTROL) => {
+            input.move_home();
+            input.redraw()?;
+        }
+        (KeyCode::End, _) | (KeyCode::Char('e'), KeyModifiers::CONTROL) => {
+            input.move_end();
+            input.redraw()?;
+        }
+        (KeyCode::Char('b'), KeyModifiers::ALT) => {
+            input.move_word_left();
+            input.redraw()?;
+        }
+        (KeyCode::Char('f'), KeyModifiers::ALT) => {
+            input.move_word_right();
+            input.redraw()?;
+        }
+        // Killed text goes in the kill ring for Ctrl-Y to paste back
+        (KeyCode::Char('w'), KeyModifiers::CONTROL) => {
+            input.kill_word_before();
+            input.redraw()?;
+        }
+        (KeyCode::Char('d'), KeyModifiers::ALT) => {
+            input.kill_word_after();
+            input.redraw()?;
+        }
+        (KeyCode::Char('u'), KeyModifiers::CONTROL) => {
+            input.kill_to_start();
+            input.redraw()?;
+        }
+        (KeyCode::Char('k'), KeyModifiers::CONTROL) => {
+            input.kill_to_end();
+            input.redraw()?;
+        }
+        (KeyCode::Char('y'), KeyModifiers::CONTROL) => {
+            input.yank();
+            input.redraw()?;
+        }
+        (KeyCode::Char('y'), KeyModifiers::ALT) => {
+            input.yank_pop();
+            input.redraw()?;
+        }
+        (KeyCode::Char('t'), KeyModifiers::CONTROL) => {
+            input.transpose();
+            input.redraw()?;
+        }
+        (KeyCode::Char('l'), KeyModifiers::CONTROL) => input.clear_screen()?,
+        // Lines already entered for a multi-line command can't be edited any more
         (KeyCode::Backspace, _) => {
-            if !input.is_empty() {
-                execute!(io::stdout(), cursor::MoveLeft(1))?;
-                execute!(io::stdout(), Clear(ClearType::UntilNewLine))?;
-            }
-            input.pop();
+            input.delete_before();
+            input.redraw()?;
+        }
+        (KeyCode::Delete, _) => {
+            input.delete_at();
+            input.redraw()?;
         }
         (KeyCode::Tab, _) => {
-            return autocomplete(input, history);
+            return autocomplete(input, shell);
+        }
+        (KeyCode::Char('r'), KeyModifiers::CONTROL) => {
+            return reverse_search(input, shell);
         }
         (KeyCode::Enter, _) | (KeyCode::Char('j'), KeyModifiers::CONTROL) => {
             disable_raw_mode()?;
             println!();
-
-            // Update our history
-            history.add_entry(input.trim().to_string());
-
-            // Parse the input
-            let parsed_input = parse_input(input.trim());
-
-            *input = String::new();
-            if parsed_input.is_empty() {
-                return Ok(InputLoop::ContinueOuter);
+            if shell.options.vi {
+                set_cursor_shape(None)?;
             }
 
-            let mut previous_output = None;
-            for input_block in parsed_input {
-                let args = input_block.args;
-                let mut buffer = vec![];
-
-                let redirect = input_block.redirect_options.redirect;
-                let redirect_bool = redirect == Redirect::Stdout || redirect == Redirect::Stderr;
-                let redirect_location = input_block.redirect_options.redirect_location;
-                let mut fileoptions = OpenOptions::new();
-
-                if redirect_bool && redirect_location.is_empty() {
-                    println!("No redirect target found");
-                    return Ok(InputLoop::ContinueOuter);
-                }
-
-                if redirect_bool {
-                    match input_block.redirect_options.redirect_type {
-                        RedirectType::Create => {
-                            fileoptions.write(true).create(true);
-                        }
-                        RedirectType::Append => {
-                            fileoptions.write(true).create(true).append(true);
-                        }
-                        RedirectType::None => {
-                            println!("No redirect type found");
-                            return Ok(InputLoop::ContinueOuter);
-                        }
-                    }
-                }
+            // Parse the input, asking for more lines while it's unfinished like an open quote or
+            // a here-document without its delimiter
+            let parsed_input = shell.parse(input.text().trim());
+            if parsed_input.as_ref().is_err_and(|e| e.is_incomplete()) {
+                input.new_line();
+                print!("{}", input.prompt());
+                io::stdout().flush().expect("Could not flush >");
+                enable_raw_mode()?;
+                return Ok(InputLoop::ContinueInner);
+            }
 
-                match input_block.command.as_str() {
-                    "echo" => {
-                        let mut echo = args.join(" ");
-                        match redirect {
-                            Redirect::Stdout | Redirect::Pipe => {
-                                echo.push('\n');
-                                buffer.write_all(echo.as_bytes())?;
-                            }
-                            _ => println!("{echo}"),
-                        }
-                    }
-                    "history" => history_fn(history, args, Some(&mut buffer), &redirect)?,
-                    "exit" => return Ok(InputLoop::Exit),
-                    "pwd" => pwd_fn(Some(&mut buffer), &redirect)?,
-                    "type" => type_fn(&args.join(" "), Some(&mut buffer), &redirect)?,
-                    "cd" => cd_fn(args, Some(&mut buffer), &redirect)?,
-                    "" => {}
-                    _ => {
-                        let child_stdout = run_program(
-                            &input_block.command,
-                            args,
-                            previous_output,
-                            &mut Some(&mut buffer),
-                            &redirect,
-                        )?;
-                        previous_output = child_stdout;
-                    }
-                }
+            // Update our history
+            shell.history.add_entry(input.text().trim().to_string());
 
-                match redirect {
-                    Redirect::Stdout | Redirect::Stderr => {
-                        let mut file = fileoptions.open(redirect_location)?;
-                        file.write_all(&buffer)?;
+            input.clear();
+            match parsed_input {
+                Ok(list) => {
+                    if let Outcome::Exit(_) = execute_list(&list, shell, &Fds::standard())? {
+                        return Ok(InputLoop::Exit);
                     }
-                    Redirect::Pipe => {
-                        if previous_output.is_none() && !buffer.is_empty() {
-                            previous_output = Some(OutputHandle::ChildBuffer(buffer.clone()));
-                        }
-                    }
-                    _ => {}
                 }
-
-                if input_block.piped {
-                    continue;
-                } else {
-                    return Ok(InputLoop::ContinueOuter);
+                Err(e) => {
+                    eprintln!("{e}");
+                    shell.last_status = 2;
                 }
             }
+            return Ok(InputLoop::ContinueOuter);
         }
+        // End of input, which leaves the shell on an empty line
+        (KeyCode::Char('d'), KeyModifiers::CONTROL) if input.is_empty() => {
+            disable_raw_mode()?;
+            if shell.options.ignoreeof {
+                println!();
+                println!("Use \"exit\" to leave the shell.");
+                return Ok(InputLoop::ContinueOuter);
+            }
+            println!("exit");
+            return Ok(InputLoop::Exit);
+        }
+        // Partway through a multi-line command, end of input means it's never going to be finished
+        (KeyCode::Char('d'), KeyModifiers::CONTROL) if input.current().is_empty() => {
+            disable_raw_mode()?;
+            println!();
+            if let Err(e) = shell.parse(input.text().trim()) {
+                eprintln!("{e}");
+            }
+            shell.history.add_entry(input.text().trim().to_string());
+            input.clear();
+            shell.last_status = 2;
+            return Ok(InputLoop::ContinueOuter);
+        }
+        // Otherwise it deletes the character under the cursor
+        (KeyCode::Char('d'), KeyModifiers::CONTROL) => {
+            input.delete_at();
+            input.redraw()?;
+        }
+        // Drop whatever's been typed, including earlier lines of a multi-line command
+        (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
+            disable_raw_mode()?;
+            println!("^C");
+            input.clear();
+            shell.history.reset_position();
+            shell.last_status = 130;
+            return Ok(InputLoop::ContinueOuter);
+        }
+        // Only jobs can be suspended, not the shell itself
+        (KeyCode::Char('z'), KeyModifiers::CONTROL) => {}
         (KeyCode::Char(c), _) => {
-            input.push(c);
-            print!("{c}");
-            io::stdout().flush().expect("Could not character");
+            input.insert(c.encode_utf8(&mut [0; 4]));
+            input.redraw()?;
         }
         _ => {}
     }
 
     Ok(InputLoop::ContinueInner)
 }
-
-pub fn parse_input(arguments: &str) -> Vec<InputBlock> {
-    let mut input_blocks = vec![];
-    let mut parsed_command = String::new();
-    let mut parsed_arguments = vec![];
-    let mut redirect = Redirect::None;
-    let mut redirect_type = RedirectType::None;
-    let mut redirect_location = String::new();
-
-    let mut single_quotes = false;
-    let mut double_quotes = false;
-    let mut escape = false;
-    let mut word = String::new();
-
-    for char in arguments.chars() {
-        match char {
-            '\'' => {
-                if double_quotes || escape {
-                    word.push(char);
-                } else {
-                    single_quotes = !single_quotes;
-                }
-            }
-            '"' => {
-                if escape && double_quotes {
-                    word.pop();
-                    word.push(char);
-                } else if escape || single_quotes {
-                    word.push(char);
-                } else {
-                    double_quotes = !double_quotes;
-                }
-            }
-            '\\' => {
-                if escape && double_quotes {
-                    word.pop();
-                    word.push(char);
-                } else if single_quotes {
-                    word.push(char);
-                } else if double_quotes {
-                    word.push(char);
-                    escape = !escape;
-                    continue;
-                } else {
-                    escape = !escape;
-                    continue;
-                }
-            }
-            ' ' => {
-                if single_quotes || double_quotes || escape {
-                    word.push(char);
-                } else if !word.is_empty() {
-                    if parsed_command.is_empty() {
-                        parsed_command = word;
-                    } else if redirect != Redirect::None {
-                        redirect_location = word;
-                    } else {
-                        match word.as_str() {
-                            "|" => {
-                                let input_block = InputBlock::new(
-                                    parsed_command.clone(),
-                                    parsed_arguments.clone(),
-                                    RedirectOptions {
-                                        redirect: Redirect::Pipe,
-                                        redirect_type,
-                                        redirect_location: redirect_location.clone(),
-                                    },
-                                    true,
-                                );
-                                input_blocks.push(input_block);
-
-                                // Reset all for a new input block
-                                parsed_command = String::new();
-                                parsed_arguments = vec![];
-                                redirect = Redirect::None;
-                                redirect_type = RedirectType::None;
-                                redirect_location = String::new();
-                            }
-                            ">" | "1>" => {
-                                redirect = Redirect::Stdout;
-                                redirect_type = RedirectType::Create;
-                            }
-                            ">>" | "1>>" => {
-                                redirect = Redirect::Stdout;
-                                redirect_type = RedirectType::Append;
-                            }
-                            "2>" => {
-                                redirect = Redirect::Stderr;
-                                redirect_type = RedirectType::Create;
-                            }
-                            "2>>" => {
-                                redirect = Redirect::Stderr;
-                                redirect_type = RedirectType::Append;
-                            }
-                            _ => {
-                                parsed_arguments.push(word.clone());
-                            }
-                        }
-                    }
-                    word = String::new();
-                }
-            }
-            _ => {
-                if double_quotes && escape {
-                    match char {
-                        '$' | '`' | '\n' => {
-                            word.pop();
-                            word.push(char);
-                        }
-                        _ => word.push(char),
-                    }
-                } else {
-                    word.push(char);
-                }
-            }
-        }
-        escape = false;
-    }
-    // push in whatever the last word was
-    if !word.is_empty() {
-        if parsed_command.is_empty() {
-            parsed_command = word;
-        } else if redirect != Redirect::None {
-            redirect_location = word;
-        } else {
-            parsed_arguments.push(word.clone());
-        }
-
-        let input_block = InputBlock::new(
-            parsed_command,
-            parsed_arguments,
-            RedirectOptions {
-                redirect,
-                redirect_type,
-                redirect_location,
-            },
-            false,
-        );
-        input_blocks.push(input_block);
-    }
-    input_blocks
-}
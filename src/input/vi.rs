@@ -0,0 +1,636 @@
+use std::io::{self, Write};
+use std::mem;
+
+use anyhow::Result;
+use crossterm::cursor::{MoveToColumn, SetCursorStyle};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use crossterm::execute;
+use crossterm::terminal::{Clear, ClearType};
+
+use crate::input::line::Line;
+use crate::input::utils::handle_key_press;
+use crate::shell::state::Shell;
+
+/// The most times a put or a move through history is repeated, so a huge count can't hang the
+/// editor
+const MAX_REPEAT: usize = 1000;
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum Mode {
+    #[default]
+    Insert,
+    Normal,
+}
+
+/// Shows which mode the editor is in with the shape of the cursor, a bar while inserting and a
+/// block otherwise. `None` gives back the terminal's own shape, for emacs mode and for commands.
+pub fn set_cursor_shape(mode: Option<Mode>) -> io::Result<()> {
+    let style = match mode {
+        Some(Mode::Insert) => SetCursorStyle::BlinkingBar,
+        Some(Mode::Normal) => SetCursorStyle::SteadyBlock,
+        None => SetCursorStyle::DefaultUserShape,
+    };
+    execute!(io::stdout(), style)
+}
+
+/// Where vi mode is up to, which carries on from one key to the next
+#[derive(Default)]
+pub struct Vi {
+    pub mode: Mode,
+    /// Digits typed before a command
+    count: Option<usize>,
+    /// `d`, `c` or `y` waiting for the motion that says what it works on, with its count
+    operator: Option<(char, Option<usize>)>,
+    /// `f`, `t`, `F`, `T` or `r` waiting for the character it takes
+    awaiting: Option<char>,
+    /// The last `f`, `t`, `F` or `T` and its character, for `;` and `,`
+    last_find: Option<(char, char)>,
+    /// What `d`, `c` and `y` last took, for `p` and `P`
+    register: String,
+    /// How the line looked before each change, for `u`
+    undo: Vec<(String, usize)>,
+    /// How the line looked before the command being typed
+    before: Option<(String, usize)>,
+    /// The keys of the command being typed, and of any text it's inserting
+    keys: Vec<KeyEvent>,
+    /// The count typed before the command, which `.` uses again unless it's given its own
+    command_count: Option<usize>,
+    /// Whether the keys typed while inserting belong to a command `.` can repeat
+    recording: bool,
+    /// The keys and count of the last change, for `.`
+    last_change: Option<(Vec<KeyEvent>, Option<usize>)>,
+    /// The last pattern `/` looked for, for `n` and `N`
+    search: String,
+    /// The pattern being typed after `/`
+    searching: Option<String>,
+}
+
+impl Vi {
+    /// Gets ready for a new line, which starts out inserting. The register, the last change and
+    /// the last search carry over.
+    pub fn reset(&mut self) {
+        *self = Vi {
+            register: mem::take(&mut self.register),
+            last_find: self.last_find,
+            last_change: self.last_change.take(),
+            search: mem::take(&mut self.search),
+            ..Vi::default()
+        };
+    }
+}
+
+/// Handles a key in vi mode, giving back whether it was used. Keys it doesn't use, which is most
+/// of them while inserting, work the same as in emacs mode.
+pub fn handle_vi_key(input: &mut Line, key: KeyEvent, shell: &mut Shell) -> Result<bool> {
+    if let Some(pattern) = input.vi.searching.take() {
+        search_key(input, key, pattern, shell)?;
+        return Ok(true);
+    }
+    match input.vi.mode {
+        Mode::Insert => insert_key(input, key, shell),
+        Mode::Normal => normal_key(input, key, shell),
+    }
+}
+
+fn insert_key(input: &mut Line, key: KeyEvent, shell: &mut Shell) -> Result<bool> {
+    let escape = KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE);
+    match (key.code, key.modifiers) {
+        (KeyCode::Esc, _) => {
+            finish_insert(input, escape)?;
+            Ok(true)
+        }
+        // Esc followed quickly by another key arrives as Alt and that key
+        (KeyCode::Char(c), KeyModifiers::ALT) => {
+            finish_insert(input, escape)?;
+            normal_key(
+                input,
+                KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE),
+                shell,
+            )
+        }
+        _ => {
+            if input.vi.recording {
+                input.vi.keys.push(key);
+            }
+            if input.vi.before.is_none() {
+                input.vi.before = Some(input.snapshot());
+            }
+            Ok(false)
+        }
+    }
+}
+
+/// Goes back to normal mode, which puts the cursor on the last character inserted
+fn finish_insert(input: &mut Line, escape: KeyEvent) -> Result<()> {
+    input.vi.mode = Mode::Normal;
+    if input.vi.recording {
+        input.vi.keys.push(escape);
+        input.vi.recording = false;
+        input.vi.last_change = Some((mem::take(&mut input.vi.keys), input.vi.command_count));
+    }
+    finish_change(input);
+    input.move_left();
+    set_cursor_shape(Some(Mode::Normal))?;
+    input.redraw()?;
+    Ok(())
+}
+
+/// Puts how the line looked before the command on the undo list, if the command changed it
+fn finish_change(input: &mut Line) {
+    if let Some(before) = input.vi.before.take()
+        && before.0 != input.text()
+    {
+        input.vi.undo.push(before);
+    }
+}
+
+fn normal_key(input: &mut Line, key: KeyEvent, shell: &mut Shell) -> Result<bool> {
+    let c = match (key.code, key.modifiers) {
+        // Running the line and the Ctrl keys work the same in both modes
+        (KeyCode::Enter, _) | (_, KeyModifiers::CONTROL) => return Ok(false),
+        (KeyCode::Esc, _) => {
+            input.vi.count = None;
+            input.vi.operator = None;
+            input.vi.awaiting = None;
+            return Ok(true);
+        }
+        (KeyCode::Char(c), _) => c,
+        (KeyCode::Left | KeyCode::Backspace, _) => 'h',
+        (KeyCode::Right, _) => 'l',
+        (KeyCode::Up, _) => 'k',
+        (KeyCode::Down, _) => 'j',
+        (KeyCode::Home, _) => '0',
+        (KeyCode::End, _) => '$',
+        (KeyCode::Delete, _) => 'x',
+        _ => return Ok(true),
+    };
+
+    let vi = &input.vi;
+    if vi.count.is_none() && vi.operator.is_none() && vi.awaiting.is_none() {
+        input.vi.before = Some(input.snapshot());
+        input.vi.keys.clear();
+    }
+    let vi = &mut input.vi;
+
+    // A count comes before the command, where `0` on its own goes to the start of the line
+    if vi.awaiting.is_none() && c.is_ascii_digit() && (c != '0' || vi.count.is_some()) {
+        let digit = c.to_digit(10).unwrap_or_default() as usize;
+        vi.count = Some(
+            vi.count
+                .unwrap_or(0)
+                .saturating_mul(10)
+                .saturating_add(digit),
+        );
+        if vi.operator.is_some() {
+            vi.keys.push(key);
+        }
+        return Ok(true);
+    }
+    if vi.keys.is_empty() {
+        vi.command_count = vi.count;
+    }
+    vi.keys.push(key);
+
+    let argument = match vi.awaiting.take() {
+        Some(command) => Some((command, c)),
+        None if matches!(c, 'f' | 't' | 'F' | 'T' | 'r') => {
+            vi.awaiting = Some(c);
+            return Ok(true);
+        }
+        None => None,
+    };
+    let (c, argument) = match argument {
+        Some((command, argument)) => (command, Some(argument)),
+        None => (c, None),
+    };
+
+    match c {
+        'u' => {
+            if let Some(before) = input.vi.undo.pop() {
+                input.restore(before);
+            }
+            input.vi.before = None;
+            input.vi.count = None;
+        }
+        '.' => {
+            let count = input.vi.count.take();
+            if let Some((keys, original)) = input.vi.last_change.clone() {
+                input.vi.count = count.or(original);
+                input.vi.keys.clear();
+                for key in keys {
+                    handle_key_press(input, key, shell)?;
+                }
+            }
+            return Ok(true);
+        }
+        _ => command(input, c, argument, shell)?,
+    }
+
+    if input.vi.operator.is_some() {
+        return Ok(true);
+    }
+    if input.vi.mode == Mode::Insert {
+        // The change carries on until Esc, which is when it's finished with
+        input.vi.recording = true;
+        set_cursor_shape(Some(Mode::Insert))?;
+    } else {
+        let changed = input
+            .vi
+            .before
+            .as_ref()
+            .is_some_and(|(text, _)| text != input.text());
+        if changed {
+            input.vi.last_change = Some((input.vi.keys.clone(), input.vi.command_count));
+        }
+        finish_change(input);
+        // Outside of inserting, the cursor stays on a character
+        let length = input.current().chars().count();
+        if input.cursor_index() >= length {
+            input.set_cursor_index(length.saturating_sub(1));
+        }
+    }
+    input.vi.count = None;
+    if input.vi.searching.is_none() {
+        input.redraw()?;
+    }
+    Ok(true)
+}
+
+/// Runs a command that's been typed in full
+fn command(input: &mut Line, c: char, argument: Option<char>, shell: &mut Shell) -> Result<()> {
+    let count = input.vi.count;
+    let times = count.unwrap_or(1);
+    let chars: Vec<char> = input.current().chars().collect();
+    let cursor = input.cursor_index();
+
+    // Commands that are short for an operator and a motion
+    let (c, operator) = match (c, input.vi.operator.take()) {
+        ('x', None) => ('l', Some(('d', None))),
+        ('X', None) => ('h', Some(('d', None))),
+        ('D', None) => ('$', Some(('d', None))),
+        ('C', None) => ('$', Some(('c', None))),
+        ('s', None) => ('l', Some(('c', None))),
+        ('S', None) => ('c', Some(('c', None))),
+        ('Y', None) => ('y', Some(('y', None))),
+        ('d' | 'c' | 'y', None) => {
+            input.vi.operator = Some((c, count));
+            input.vi.count = None;
+            return Ok(());
+        }
+        (c, operator) => (c, operator),
+    };
+
+    if let Some((operator, operator_count)) = operator {
+        // Counts on the operator and the motion multiply, like `2d3w` deleting six words
+        let times = operator_count.unwrap_or(1).saturating_mul(times);
+        let range = match c {
+            // Doubling the operator works on the whole line
+            _ if c == operator => Some((0, chars.len())),
+            // `cw` stops at the end of the word rather than going on to the next one
+            'w' | 'W'
+                if operator == 'c' && chars.get(cursor).is_some_and(|c| !c.is_whitespace()) =>
+            {
+                let big = c == 'W';
+                let word = class(chars[cursor], big);
+                let mut end = match chars.get(cursor + 1) {
+                    Some(next) if class(*next, big) == word => word_end(&chars, cursor, big),
+                    _ => cursor,
+                };
+                for _ in 1..times.min(chars.len()) {
+                    end = word_end(&chars, end, big);
+                }
+                Some((cursor, end + 1))
+            }
+            _ => motion(input, c, argument, times, &chars).map(|(target, inclusive)| {
+                let (start, end) = (cursor.min(target), cursor.max(target));
+                (start, (end + inclusive as usize).min(chars.len()))
+            }),
+        };
+        let Some((start, end)) = range else {
+            return Ok(());
+        };
+
+        input.vi.register = chars[start..end].iter().collect();
+        match operator {
+            'y' if c == operator => {}
+            'y' => input.set_cursor_index(start),
+            _ => input.replace(start, end, ""),
+        }
+        if operator == 'c' {
+            input.vi.mode = Mode::Insert;
+        }
+        return Ok(());
+    }
+
+    match c {
+        'i' => input.vi.mode = Mode::Insert,
+        'a' => {
+            input.set_cursor_index(cursor + 1);
+            input.vi.mode = Mode::Insert;
+        }
+        'I' => {
+            input.set_cursor_index(first_non_blank(&chars));
+            input.vi.mode = Mode::Insert;
+        }
+        'A' => {
+            input.set_cursor_index(chars.len());
+            input.vi.mode = Mode::Insert;
+        }
+        'p' | 'P' => {
+            if input.vi.register.is_empty() {
+                return Ok(());
+            }
+            let text = input.vi.register.repeat(times.min(MAX_REPEAT));
+            let at = match c {
+                'p' if !chars.is_empty() => cursor + 1,
+                _ => cursor,
+            };
+            input.set_cursor_index(at);
+            input.insert(&text);
+            input.move_left();
+        }
+        'r' => {
+            let Some(replacement) = argument else {
+                return Ok(());
+            };
+            if times <= chars.len() - cursor {
+                let text: String = std::iter::repeat_n(replacement, times).collect();
+                input.replace(cursor, cursor + times, &text);
+                input.set_cursor_index(cursor + times - 1);
+            }
+        }
+        '~' => {
+            let end = cursor.saturating_add(times).min(chars.len());
+            let toggled: String = chars[cursor..end]
+                .iter()
+                .flat_map(|c| match c.is_uppercase() {
+                    true => c.to_lowercase().collect::<Vec<_>>(),
+                    false => c.to_uppercase().collect(),
+                })
+                .collect();
+            input.replace(cursor, end, &toggled);
+            input.set_cursor_index(end);
+        }
+        'k' | '-' | 'j' | '+' => {
+            for _ in 0..times.min(MAX_REPEAT) {
+                let entry = match c {
+                    'k' | '-' => shell.history.move_up(input.current()),
+                    _ => shell.history.move_down(),
+                };
+                if let Some(entry) = entry.cloned() {
+                    input.set_current(&entry);
+                }
+            }
+            input.set_cursor_index(0);
+            input.vi.before = None;
+        }
+        '/' => {
+            input.vi.searching = Some(String::new());
+            draw_search("")?;
+        }
+        'n' | 'N' => {
+            let pattern = input.vi.search.clone();
+            search(input, &pattern, c == 'n', shell);
+        }
+        _ => {
+            if let Some((target, _)) = motion(input, c, argument, times, &chars) {
+                input.set_cursor_index(target);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Where a motion takes the cursor, and whether the character it lands on is included when an
+/// operator uses it
+fn motion(
+    input: &mut Line,
+    c: char,
+    argument: Option<char>,
+    times: usize,
+    chars: &[char],
+) -> Option<(usize, bool)> {
+    let cursor = input.cursor_index();
+    // No motion can take more steps than there are characters to go over
+    let times = times.min(chars.len() + 1);
+    let repeat = |step: &dyn Fn(usize) -> usize| (0..times).fold(cursor, |at, _| step(at));
+    Some(match c {
+        'h' => (cursor.saturating_sub(times), false),
+        'l' | ' ' => ((cursor + times).min(chars.len()), false),
+        '0' => (0, false),
+        '^' => (first_non_blank(chars), false),
+        '$' => (chars.len().saturating_sub(1), true),
+        'w' | 'W' => (repeat(&|at| next_word_start(chars, at, c == 'W')), false),
+        'b' | 'B' => (repeat(&|at| word_start(chars, at, c == 'B')), false),
+        'e' | 'E' => (repeat(&|at| word_end(chars, at, c == 'E')), true),
+        'f' | 't' | 'F' | 'T' => {
+            let target = argument?;
+            input.vi.last_find = Some((c, target));
+            find(chars, cursor, c, target, times)?
+        }
+        // Repeats the last find, the same way or the other way round
+        ';' | ',' => {
+            let (find_command, target) = input.vi.last_find?;
+            let find_command = match c {
+                ';' => find_command,
+                _ if find_command.is_lowercase() => find_command.to_ascii_uppercase(),
+                _ => find_command.to_ascii_lowercase(),
+            };
+            find(chars, cursor, find_command, target, times)?
+        }
+        _ => return None,
+    })
+}
+
+/// Finds the `times`th `target` after the cursor with `f` and `t`, or before it with `F` and `T`,
+/// where `t` and `T` stop one short of it
+fn find(
+    chars: &[char],
+    cursor: usize,
+    c: char,
+    target: char,
+    times: usize,
+) -> Option<(usize, bool)> {
+    match c {
+        'f' | 't' => {
+            let found = (cursor + 1..chars.len())
+                .filter(|i| chars[*i] == target)
+                .nth(times - 1)?;
+            Some((if c == 't' { found - 1 } else { found }, true))
+        }
+        _ => {
+            let found = (0..cursor)
+                .rev()
+                .filter(|i| chars[*i] == target)
+                .nth(times - 1)?;
+            Some((if c == 'T' { found + 1 } else { found }, false))
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Class {
+    Blank,
+    Word,
+    Punctuation,
+}
+
+/// Words are runs of letters, digits and underscores or runs of other punctuation, unless it's
+/// one of the `W`, `B` and `E` motions where anything but whitespace is part of a word
+fn class(c: char, big: bool) -> Class {
+    if c.is_whitespace() {
+        Class::Blank
+    } else if big || c.is_alphanumeric() || c == '_' {
+        Class::Word
+    } else {
+        Class::Punctuation
+    }
+}
+
+fn first_non_blank(chars: &[char]) -> usize {
+    chars
+        .iter()
+        .position(|c| !c.is_whitespace())
+        .unwrap_or(chars.len())
+}
+
+fn next_word_start(chars: &[char], mut at: usize, big: bool) -> usize {
+    if let Some(start) = chars.get(at).map(|c| class(*c, big))
+        && start != Class::Blank
+    {
+        while chars.get(at).is_some_and(|c| class(*c, big) == start) {
+            at += 1;
+        }
+    }
+    while chars.get(at).is_some_and(|c| c.is_whitespace()) {
+        at += 1;
+    }
+    at
+}
+
+fn word_start(chars: &[char], mut at: usize, big: bool) -> usize {
+    while at > 0 && chars[at - 1].is_whitespace() {
+        at -= 1;
+    }
+    let Some(word) = at.checked_sub(1).map(|i| class(chars[i], big)) else {
+        return 0;
+    };
+    while at > 0 && class(chars[at - 1], big) == word {
+        at -= 1;
+    }
+    at
+}
+
+fn word_end(chars: &[char], mut at: usize, big: bool) -> usize {
+    at += 1;
+    while chars.get(at).is_some_and(|c| c.is_whitespace()) {
+        at += 1;
+    }
+    let Some(word) = chars.get(at).map(|c| class(*c, big)) else {
+        return chars.len().saturating_sub(1);
+    };
+    while chars.get(at + 1).is_some_and(|c| class(*c, big) == word) {
+        at += 1;
+    }
+    at
+}
+
+/// Handles a key while the pattern after `/` is being typed
+fn search_key(
+    input: &mut Line,
+    key: KeyEvent,
+    mut pattern: String,
+    shell: &mut Shell,
+) -> Result<()> {
+    match key.code {
+        KeyCode::Enter => {
+            // An empty pattern looks for the last one again
+            if !pattern.is_empty() {
+                input.vi.search = pattern;
+            }
+            let pattern = input.vi.search.clone();
+            search(input, &pattern, true, shell);
+            return Ok(input.redraw()?);
+        }
+        KeyCode::Esc => return Ok(input.redraw()?),
+        KeyCode::Backspace if pattern.pop().is_none() => return Ok(input.redraw()?),
+        KeyCode::Char(c) if key.modifiers == KeyModifiers::CONTROL && c == 'c' => {
+            return Ok(input.redraw()?);
+        }
+        KeyCode::Char(c) => pattern.push(c),
+        _ => {}
+    }
+    draw_search(&pattern)?;
+    input.vi.searching = Some(pattern);
+    Ok(())
+}
+
+fn draw_search(pattern: &str) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    execute!(stdout, MoveToColumn(0), Clear(ClearType::CurrentLine))?;
+    write!(stdout, "/{pattern}")?;
+    stdout.flush()
+}
+
+/// Recalls the closest older history entry containing `pattern`, or newer one if it's not
+/// `older`, ringing the bell if there isn't one
+fn search(input: &mut Line, pattern: &str, older: bool, shell: &mut Shell) {
+    let entry = match older {
+        true => shell.history.search_up(pattern),
+        false => shell.history.search_down(pattern),
+    };
+    match entry.filter(|_| !pattern.is_empty()) {
+        Some(entry) => {
+            let entry = entry.clone();
+            input.set_current(&entry);
+            input.set_cursor_index(0);
+            input.vi.before = None;
+        }
+        None => {
+            print!("\x07");
+            let _ = io::stdout().flush();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builtins::history::History;
+
+    /// Types `keys` in normal mode on a line that starts out as `text`
+    fn normal_keys(text: &str, keys: &str) -> String {
+        let mut shell = Shell::new(History::new());
+        let mut input = Line::default();
+        input.insert(text);
+        let escape = KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE);
+        handle_vi_key(&mut input, escape, &mut shell).unwrap();
+        for c in keys.chars() {
+            let key = KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE);
+            handle_vi_key(&mut input, key, &mut shell).unwrap();
+        }
+        input.text().to_string()
+    }
+
+    #[test]
+    fn counts() {
+        assert_eq!(normal_keys("one two three", "0dw"), "two three");
+        assert_eq!(normal_keys("one two three four", "02d1w"), "three four");
+        assert_eq!(normal_keys("abc", "03x"), "");
+        assert_eq!(normal_keys("abc", "0yl3p"), "aaaabc");
+    }
+
+    #[test]
+    fn huge_counts() {
+        let huge = "99999999999999999999";
+        assert_eq!(
+            normal_keys("echo hello world", &format!("0{huge}d{huge}w")),
+            ""
+        );
+        assert_eq!(normal_keys("abc", &format!("0{huge}l{huge}rZ")), "abc");
+        assert_eq!(normal_keys("abc", &format!("0{huge}~")), "ABC");
+        assert_eq!(normal_keys("abc", &format!("0{huge}k{huge}j")), "abc");
+        let put = normal_keys("abc", &format!("0yl{huge}p"));
+        assert_eq!(put.len(), 3 + MAX_REPEAT);
+    }
+}
@@ -1,34 +1,151 @@
-use std::io::{self, Write};
+use std::env;
+use std::fs::File;
+use std::io::{self, BufReader, IsTerminal, Write};
+use std::os::fd::AsFd;
+use std::path::PathBuf;
+use std::process;
 
 use anyhow::Result;
 use crossterm::event::{Event, read};
-use crossterm::terminal::enable_raw_mode;
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
 use crossterm::{cursor::MoveToColumn, execute};
 
 use crate::builtins::history::History;
+use crate::input::line::Line;
+use crate::input::script::run_script;
 use crate::input::utils::{InputLoop, handle_key_press};
+use crate::input::vi::{Mode, set_cursor_shape};
+use crate::shell::state::Shell;
+use crate::subprocesses::executor::Outcome;
+use crate::subprocesses::fds::Fds;
+use crate::subprocesses::job_control::init_job_control;
 
 mod builtins;
+mod expansion;
 mod input;
+mod parser;
+mod shell;
 mod subprocesses;
 
 fn main() -> Result<()> {
-    let mut input = String::new();
-    let mut history = match History::read_from_env() {
-        Ok(history) => history,
-        Err(_) => History::new(),
+    let mut arguments: Vec<String> = env::args().collect();
+    let mut shell = Shell::new(History::new());
+
+    // A login shell is started with `-` in front of its name, or asked to be one with `-l`
+    let mut login = arguments[0].starts_with('-');
+    if let Some(flag) = arguments.get(1)
+        && (flag == "-l" || flag == "--login")
+    {
+        login = true;
+        arguments.remove(1);
+    }
+    if login {
+        let profile = home_file(&shell, ".rustsh_profile");
+        if let Outcome::Exit(status) = run_startup_file(&mut shell, profile)? {
+            process::exit(status);
+        }
+    }
+
+    let outcome = match arguments.get(1).map(String::as_str) {
+        Some("-c") => {
+            let Some(command) = arguments.get(2) else {
+                eprintln!("rustsh: -c: option requires an argument");
+                process::exit(2);
+            };
+            // Like other shells, the first argument after the command is `$0`
+            if let Some(name) = arguments.get(3) {
+                shell.script_name = name.clone();
+            }
+            shell.positional = arguments.iter().skip(4).cloned().collect();
+            run_script(
+                &mut shell,
+                command.as_bytes(),
+                "rustsh: -c",
+                &Fds::standard(),
+            )?
+        }
+        Some(option) if option.starts_with('-') => {
+            eprintln!("rustsh: {option}: invalid option");
+            process::exit(2);
+        }
+        Some(path) => {
+            let file = match File::open(path) {
+                Ok(file) => file,
+                Err(e) => {
+                    eprintln!("rustsh: {path}: {e}");
+                    process::exit(127);
+                }
+            };
+            shell.script_name = path.to_string();
+            shell.positional = arguments.iter().skip(2).cloned().collect();
+            run_script(&mut shell, BufReader::new(file), path, &Fds::standard())?
+        }
+        None if !io::stdin().is_terminal() => {
+            // Read a byte at a time so that whatever comes after the line being run is still
+            // there for the commands it starts
+            let stdin = File::from(io::stdin().as_fd().try_clone_to_owned()?);
+            let reader = BufReader::with_capacity(1, stdin);
+            run_script(&mut shell, reader, "rustsh", &Fds::standard())?
+        }
+        None => run_interactive(&mut shell)?,
+    };
+    process::exit(outcome.status());
+}
+
+fn home_file(shell: &Shell, name: &str) -> Option<PathBuf> {
+    shell
+        .variables
+        .get("HOME")
+        .map(|home| PathBuf::from(home).join(name))
+}
+
+/// Runs a file like `~/.rustshrc` in the shell before anything else, if it's there
+fn run_startup_file(shell: &mut Shell, path: Option<PathBuf>) -> Result<Outcome> {
+    let Some(path) = path else {
+        return Ok(Outcome::Status(0));
+    };
+    let Ok(file) = File::open(&path) else {
+        return Ok(Outcome::Status(0));
     };
+    let name = path.to_string_lossy();
+    run_script(shell, BufReader::new(file), &name, &Fds::standard())
+}
+
+/// Reads commands from the terminal with the line editor until the shell is told to exit
+fn run_interactive(shell: &mut Shell) -> Result<Outcome> {
+    let mut input = Line::default();
+    shell.terminal = init_job_control();
+    shell.interactive = true;
+    shell.options.expand_aliases = true;
+    shell.options.emacs = true;
+
+    let rc = match shell.variables.get("RUSTSH_RC") {
+        Some(rc) => Some(PathBuf::from(rc)),
+        None => home_file(shell, ".rustshrc"),
+    };
+    if let Outcome::Exit(status) = run_startup_file(shell, rc)? {
+        return Ok(Outcome::Exit(status));
+    }
+    // The rc file gets the chance to set HISTFILE first
+    let _ = shell.history.read_from_histfile(&shell.variables);
 
     'outer: loop {
+        for report in shell.jobs.report_changes() {
+            println!("{report}");
+        }
+
         execute!(io::stdout(), MoveToColumn(0))?;
         print!("$ ");
         io::stdout().flush().expect("Could not flush $");
+        if shell.options.vi {
+            set_cursor_shape(Some(Mode::Insert))?;
+        }
 
         enable_raw_mode()?;
 
         loop {
             if let Ok(Event::Key(key_event)) = read() {
-                let inputloop = handle_key_press(&mut input, key_event, &mut history)?;
+                let inputloop = handle_key_press(&mut input, key_event, shell)?;
                 match inputloop {
                     InputLoop::ContinueOuter => continue 'outer,
                     InputLoop::ContinueInner => {}
@@ -37,6 +154,10 @@ fn main() -> Result<()> {
             }
         }
     }
-    let _ = history.write_to_env().is_ok();
-    Ok(())
+    disable_raw_mode()?;
+    if shell.options.vi {
+        set_cursor_shape(None)?;
+    }
+    let _ = shell.history.write_to_histfile(&shell.variables);
+    Ok(Outcome::Exit(shell.last_status))
 }
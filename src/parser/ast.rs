@@ -0,0 +1,358 @@
+use std::fmt;
+
+/// The forms of `${...}`, where `colon` means an empty value is treated the same as an unset one
+#[derive(Clone, PartialEq, Debug)]
+pub enum ParameterOp {
+    None,
+    Length,
+    Default { colon: bool, word: Word },
+    Assign { colon: bool, word: Word },
+    Error { colon: bool, word: Word },
+    Alternative { colon: bool, word: Word },
+    RemovePrefix { longest: bool, pattern: Word },
+    RemoveSuffix { longest: bool, pattern: Word },
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Parameter {
+    pub name: String,
+    pub op: ParameterOp,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum WordPart {
+    Literal(String),
+    Quoted(String),
+    DoubleQuoted(Vec<WordPart>),
+    Parameter(Parameter),
+    /// The source of a `$(...)` or backquoted command, which is only parsed when it's expanded
+    CommandSubstitution(String),
+    /// The expression in `$((...))`, which is expanded before it's evaluated
+    Arithmetic(Word),
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Word {
+    pub raw: String,
+    pub parts: Vec<WordPart>,
+}
+
+impl Word {
+    /// Returns the word's text if it has no quoting, which is what reserved words like `{` require
+    pub fn as_unquoted(&self) -> Option<&str> {
+        match self.parts.as_slice() {
+            [WordPart::Literal(text)] => Some(text),
+            _ => None,
+        }
+    }
+
+    pub fn is_reserved(&self, reserved: &str) -> bool {
+        self.as_unquoted() == Some(reserved)
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum RedirectKind {
+    Output,
+    Append,
+    Input,
+    ReadWrite,
+    /// `<<` and `<<-`, where the target is the body of the here-document
+    HereDoc,
+    HereString,
+    /// `n>&m` and `n<&m`, or closing the descriptor when the target is `-`
+    DupOutput,
+    DupInput,
+    /// `&>` and `&>>`, which send both stdout and stderr to the file
+    OutputAll,
+    AppendAll,
+}
+
+#[derive(Clone, Debug)]
+pub struct Redirection {
+    pub fd: i32,
+    pub kind: RedirectKind,
+    pub target: Word,
+}
+
+/// A `NAME=value` word at the start of a simple command
+#[derive(Clone, Debug)]
+pub struct Assignment {
+    pub name: String,
+    pub value: Word,
+}
+
+#[derive(Clone, Debug)]
+pub struct SimpleCommand {
+    pub assignments: Vec<Assignment>,
+    pub words: Vec<Word>,
+    pub redirections: Vec<Redirection>,
+}
+
+/// One `pattern | pattern) commands ;;` arm of a `case`
+#[derive(Clone, Debug)]
+pub struct CaseItem {
+    pub patterns: Vec<Word>,
+    pub body: List,
+}
+
+/// An expression inside `[[ ]]`, whose words aren't split or matched against paths
+#[derive(Clone, Debug)]
+pub enum Conditional {
+    /// A word on its own, which is true when it isn't empty
+    Word(Word),
+    /// A test like `-f path`
+    Unary(String, Word),
+    /// A comparison like `a == pattern` or `a -lt b`
+    Binary(String, Word, Word),
+    Not(Box<Conditional>),
+    And(Box<Conditional>, Box<Conditional>),
+    Or(Box<Conditional>, Box<Conditional>),
+}
+
+#[derive(Clone, Debug)]
+pub enum CompoundCommand {
+    BraceGroup(List),
+    Subshell(List),
+    /// The `if` and `elif` conditions with the commands each one runs, and the `else` commands
+    If {
+        branches: Vec<(List, List)>,
+        otherwise: Option<List>,
+    },
+    /// `while`, or `until` when the condition has to fail for the body to run
+    While {
+        until: bool,
+        condition: List,
+        body: List,
+    },
+    /// `for name in words`, where leaving out `in` loops over the positional parameters
+    For {
+        name: String,
+        words: Option<Vec<Word>>,
+        body: List,
+    },
+    Case {
+        word: Word,
+        items: Vec<CaseItem>,
+    },
+    /// `[[ expression ]]`
+    Conditional(Conditional),
+    /// `((expression))`, which succeeds when the expression isn't 0
+    Arithmetic(Word),
+}
+
+#[derive(Clone, Debug)]
+pub enum Command {
+    Simple(SimpleCommand),
+    Compound(CompoundCommand, Vec<Redirection>),
+    /// `name() body`, where the body is a compound command along with its redirections
+    Function {
+        name: String,
+        body: Box<Command>,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct Pipeline {
+    /// Started with `!`, which turns success into failure and the other way round
+    pub negated: bool,
+    pub commands: Vec<Command>,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Connector {
+    And,
+    Or,
+}
+
+/// Pipelines joined by `&&` and `||`, which only run depending on the status of the previous one
+#[derive(Clone, Debug)]
+pub struct AndOr {
+    pub first: Pipeline,
+    pub rest: Vec<(Connector, Pipeline)>,
+    /// Ended with `&`, so it runs as a job without the shell waiting for it
+    pub background: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct List {
+    pub items: Vec<AndOr>,
+}
+
+// Commands are written back out the way they'd be typed, for things like listing jobs
+
+impl fmt::Display for Redirection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (operator, default_fd) = match self.kind {
+            RedirectKind::Output => ("> ", Some(1)),
+            RedirectKind::Append => (">> ", Some(1)),
+            RedirectKind::Input => ("< ", Some(0)),
+            RedirectKind::ReadWrite => ("<> ", Some(0)),
+            RedirectKind::HereDoc => ("<<", Some(0)),
+            RedirectKind::HereString => ("<<< ", Some(0)),
+            RedirectKind::DupOutput => (">&", Some(1)),
+            RedirectKind::DupInput => ("<&", Some(0)),
+            RedirectKind::OutputAll => ("&> ", None),
+            RedirectKind::AppendAll => ("&>> ", None),
+        };
+        if default_fd.is_some_and(|fd| fd != self.fd) {
+            write!(f, "{}", self.fd)?;
+        }
+        write!(f, "{operator}{}", self.target.raw)
+    }
+}
+
+impl fmt::Display for SimpleCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let assignments = self
+            .assignments
+            .iter()
+            .map(|assignment| format!("{}={}", assignment.name, assignment.value.raw));
+        let words = self.words.iter().map(|word| word.raw.clone());
+        let redirections = self
+            .redirections
+            .iter()
+            .map(|redirection| redirection.to_string());
+        let parts: Vec<String> = assignments.chain(words).chain(redirections).collect();
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+impl fmt::Display for Conditional {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // `||` binds less tightly than `&&`, and both less tightly than `!`, so those need
+        // parentheses to keep their grouping
+        let grouped =
+            |f: &mut fmt::Formatter<'_>, expression: &Conditional, loose: bool| match expression {
+                Conditional::Or(..) => write!(f, "( {expression} )"),
+                Conditional::And(..) if loose => write!(f, "( {expression} )"),
+                _ => write!(f, "{expression}"),
+            };
+        match self {
+            Conditional::Word(word) => write!(f, "{}", word.raw),
+            Conditional::Unary(op, word) => write!(f, "{op} {}", word.raw),
+            Conditional::Binary(op, left, right) => write!(f, "{} {op} {}", left.raw, right.raw),
+            Conditional::Not(expression) => {
+                write!(f, "! ")?;
+                grouped(f, expression, true)
+            }
+            Conditional::And(left, right) => {
+                grouped(f, left, false)?;
+                write!(f, " && ")?;
+                grouped(f, right, false)
+            }
+            Conditional::Or(left, right) => write!(f, "{left} || {right}"),
+        }
+    }
+}
+
+impl fmt::Display for CompoundCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompoundCommand::BraceGroup(list) => write!(f, "{{ {list}; }}"),
+            CompoundCommand::Subshell(list) => write!(f, "({list})"),
+            CompoundCommand::If {
+                branches,
+                otherwise,
+            } => {
+                for (i, (condition, body)) in branches.iter().enumerate() {
+                    let keyword = if i == 0 { "if" } else { "elif" };
+                    write!(f, "{keyword} {condition}; then {body}; ")?;
+                }
+                if let Some(otherwise) = otherwise {
+                    write!(f, "else {otherwise}; ")?;
+                }
+                write!(f, "fi")
+            }
+            CompoundCommand::While {
+                until,
+                condition,
+                body,
+            } => {
+                let keyword = if *until { "until" } else { "while" };
+                write!(f, "{keyword} {condition}; do {body}; done")
+            }
+            CompoundCommand::For { name, words, body } => {
+                write!(f, "for {name}")?;
+                if let Some(words) = words {
+                    write!(f, " in")?;
+                    for word in words {
+                        write!(f, " {}", word.raw)?;
+                    }
+                }
+                write!(f, "; do {body}; done")
+            }
+            CompoundCommand::Case { word, items } => {
+                write!(f, "case {} in", word.raw)?;
+                for item in items {
+                    let patterns: Vec<&str> =
+                        item.patterns.iter().map(|p| p.raw.as_str()).collect();
+                    write!(f, " {}) {};;", patterns.join(" | "), item.body)?;
+                }
+                write!(f, " esac")
+            }
+            CompoundCommand::Conditional(expression) => write!(f, "[[ {expression} ]]"),
+            CompoundCommand::Arithmetic(expression) => write!(f, "(({}))", expression.raw),
+        }
+    }
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Command::Simple(simple_command) => write!(f, "{simple_command}"),
+            Command::Compound(compound, redirections) => {
+                write!(f, "{compound}")?;
+                for redirection in redirections {
+                    write!(f, " {redirection}")?;
+                }
+                Ok(())
+            }
+            Command::Function { name, body } => write!(f, "{name} () {body}"),
+        }
+    }
+}
+
+impl fmt::Display for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let commands: Vec<String> = self.commands.iter().map(|c| c.to_string()).collect();
+        if self.negated {
+            write!(f, "! ")?;
+        }
+        write!(f, "{}", commands.join(" | "))
+    }
+}
+
+impl fmt::Display for AndOr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.first)?;
+        for (connector, pipeline) in &self.rest {
+            match connector {
+                Connector::And => write!(f, " && {pipeline}")?,
+                Connector::Or => write!(f, " || {pipeline}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for List {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, and_or) in self.items.iter().enumerate() {
+            if i > 0 {
+                // `&` already separates a background item from the next one
+                let separator = if self.items[i - 1].background {
+                    " "
+                } else {
+                    "; "
+                };
+                write!(f, "{separator}")?;
+            }
+            write!(f, "{and_or}")?;
+            if and_or.background {
+                write!(f, " &")?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,911 @@
+use std::fmt;
+
+use crate::parser::ast::{Parameter, ParameterOp, Word, WordPart};
+use crate::parser::utils::ParseError;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Operator {
+    Pipe,
+    AndIf,
+    OrIf,
+    Semi,
+    DSemi,
+    Amp,
+    LParen,
+    RParen,
+    Less,
+    Great,
+    DGreat,
+    DLess,
+    DLessDash,
+    LessAnd,
+    GreatAnd,
+    LessGreat,
+    Clobber,
+    TLess,
+    AndGreat,
+    AndDGreat,
+}
+
+// Longest operators first so that `>>` wins over `>`
+const OPERATORS: [(&str, Operator); 20] = [
+    ("<<<", Operator::TLess),
+    ("<<-", Operator::DLessDash),
+    ("&>>", Operator::AndDGreat),
+    ("&&", Operator::AndIf),
+    ("||", Operator::OrIf),
+    (";;", Operator::DSemi),
+    ("<<", Operator::DLess),
+    (">>", Operator::DGreat),
+    ("<&", Operator::LessAnd),
+    (">&", Operator::GreatAnd),
+    ("<>", Operator::LessGreat),
+    (">|", Operator::Clobber),
+    ("&>", Operator::AndGreat),
+    ("|", Operator::Pipe),
+    ("&", Operator::Amp),
+    (";", Operator::Semi),
+    ("(", Operator::LParen),
+    (")", Operator::RParen),
+    ("<", Operator::Less),
+    (">", Operator::Great),
+];
+
+impl Operator {
+    pub fn as_str(&self) -> &'static str {
+        OPERATORS
+            .iter()
+            .find(|(_, operator)| operator == self)
+            .map(|(text, _)| *text)
+            .expect("Every operator is in the table")
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Token {
+    Word(Word),
+    IoNumber(i32),
+    Operator(Operator),
+    Newline,
+    /// The body of a here-document, which takes the place of its delimiter
+    HereDoc(Word),
+    /// The expression in a `((...))` command
+    Arithmetic(Word),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "{}", word.raw),
+            Token::IoNumber(n) => write!(f, "{n}"),
+            Token::Operator(operator) => write!(f, "{}", operator.as_str()),
+            Token::Newline => write!(f, "newline"),
+            Token::HereDoc(body) => write!(f, "{}", body.raw),
+            Token::Arithmetic(expression) => write!(f, "(({}))", expression.raw),
+        }
+    }
+}
+
+/// How far through a `case` the scan for the end of a `$(` is
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum CaseScan {
+    /// Before the `in`
+    Word,
+    Pattern,
+    /// The commands after a pattern, up to its `;;`
+    Body,
+}
+
+fn is_operator_start(c: char) -> bool {
+    matches!(c, '|' | '&' | ';' | '<' | '>' | '(' | ')')
+}
+
+fn is_blank(c: char) -> bool {
+    c == ' ' || c == '\t'
+}
+
+fn is_name_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_'
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+fn is_special_parameter(c: char) -> bool {
+    matches!(c, '?' | '$' | '#' | '@' | '*' | '!' | '-')
+}
+
+/// Words that mean something to the parser when they start a command
+pub const RESERVED_WORDS: [&str; 19] = [
+    "if", "then", "elif", "else", "fi", "while", "until", "do", "done", "for", "in", "case",
+    "esac", "function", "{", "}", "[[", "]]", "!",
+];
+
+/// Whether `name` can be assigned to, which rules out the positional and special parameters
+pub fn is_variable_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(is_name_start) && chars.all(is_name_char)
+}
+
+/// Whether `name` can follow a `$`, either as a variable name, a positional parameter or one of
+/// the special parameters
+pub fn is_parameter_name(name: &str) -> bool {
+    if let (name, Some(subscript)) = split_subscript(name) {
+        return is_variable_name(name) && !subscript.is_empty();
+    }
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if is_name_start(c) => is_variable_name(name),
+        Some(c) if c.is_ascii_digit() => chars.all(|c| c.is_ascii_digit()),
+        Some(c) if is_special_parameter(c) => chars.next().is_none(),
+        _ => false,
+    }
+}
+
+/// Splits an array element like `name[1]` into the name and the subscript
+pub fn split_subscript(name: &str) -> (&str, Option<&str>) {
+    match name.strip_suffix(']').and_then(|name| name.split_once('[')) {
+        Some((name, subscript)) => (name, Some(subscript)),
+        None => (name, None),
+    }
+}
+
+pub struct Lexer {
+    chars: Vec<char>,
+    position: usize,
+    /// Set while reading the regex after `=~` in `[[ ]]`, to how deeply nested in parentheses it is,
+    /// since parentheses and `|` are part of the regex rather than operators
+    regex_depth: Option<usize>,
+}
+
+impl Lexer {
+    pub fn new(input: &str) -> Lexer {
+        Lexer {
+            chars: input.chars().collect(),
+            position: 0,
+            regex_depth: None,
+        }
+    }
+
+    /// Whether `c` ends the word being read
+    fn ends_word(&mut self, c: char) -> bool {
+        if let Some(depth) = &mut self.regex_depth {
+            match c {
+                '(' => {
+                    *depth += 1;
+                    return false;
+                }
+                ')' if *depth > 0 => {
+                    *depth -= 1;
+                    return false;
+                }
+                '|' => return false,
+                _ => {}
+            }
+        }
+        is_blank(c) || c == '\n' || is_operator_start(c)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.position + offset).copied()
+    }
+
+    fn starts_with(&self, text: &str) -> bool {
+        text.chars()
+            .enumerate()
+            .all(|(i, c)| self.peek_at(i) == Some(c))
+    }
+
+    pub fn tokenize(mut self) -> Result<Vec<Token>, ParseError> {
+        let mut tokens = vec![];
+        // Delimiters of here-documents whose bodies start after the next newline
+        let mut pending_heredocs = vec![];
+        // Inside of `[[ ]]`, where the word after `=~` is a regex
+        let mut conditional = false;
+
+        while let Some(c) = self.peek() {
+            if is_blank(c) {
+                self.position += 1;
+            } else if c == '\\' && self.peek_at(1) == Some('\n') {
+                // Line continuation
+                self.position += 2;
+            } else if c == '#' {
+                while let Some(c) = self.peek() {
+                    if c == '\n' {
+                        break;
+                    }
+                    self.position += 1;
+                }
+            } else if c == '\n' {
+                self.position += 1;
+                tokens.push(Token::Newline);
+                for (index, strip_tabs) in pending_heredocs.drain(..) {
+                    let Some(Token::Word(delimiter)) = tokens.get(index) else {
+                        continue;
+                    };
+                    let body = self.read_heredoc(delimiter, strip_tabs)?;
+                    tokens[index] = Token::HereDoc(body);
+                }
+            } else if conditional
+                && matches!(tokens.last(), Some(Token::Word(word)) if word.is_reserved("=~"))
+            {
+                self.regex_depth = Some(0);
+                let regex = self.read_word();
+                self.regex_depth = None;
+                tokens.push(Token::Word(regex?));
+            } else if let Some(expression) = self.read_arithmetic()? {
+                tokens.push(Token::Arithmetic(expression));
+            } else if is_operator_start(c) {
+                let (text, operator) = OPERATORS
+                    .iter()
+                    .find(|(text, _)| self.starts_with(text))
+                    .expect("Operator start characters are all single char operators");
+                self.position += text.chars().count();
+                tokens.push(Token::Operator(*operator));
+            } else {
+                let word = self.read_word()?;
+                let is_io_number = word.raw.chars().all(|c| c.is_ascii_digit())
+                    && matches!(self.peek(), Some('<') | Some('>'));
+                if word.is_reserved("[[") {
+                    conditional = true;
+                } else if word.is_reserved("]]") {
+                    conditional = false;
+                }
+                match word.raw.parse::<i32>() {
+                    Ok(n) if is_io_number => tokens.push(Token::IoNumber(n)),
+                    _ => tokens.push(Token::Word(word)),
+                }
+
+                let heredoc_operator = tokens.len().checked_sub(2).map(|i| &tokens[i]);
+                match heredoc_operator {
+                    Some(Token::Operator(Operator::DLess)) => {
+                        pending_heredocs.push((tokens.len() - 1, false))
+                    }
+                    Some(Token::Operator(Operator::DLessDash)) => {
+                        pending_heredocs.push((tokens.len() - 1, true))
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        if let Some((index, _)) = pending_heredocs.first() {
+            return Err(ParseError::IncompleteHereDoc(tokens[*index].to_string()));
+        }
+        Ok(tokens)
+    }
+
+    /// Reads the lines of a here-document up to its delimiter. Quoting any part of the delimiter
+    /// means the body is taken literally, otherwise it's expanded like it was in double quotes.
+    fn read_heredoc(&mut self, delimiter: &Word, strip_tabs: bool) -> Result<Word, ParseError> {
+        let mut end = String::new();
+        let mut quoted = false;
+        if !push_delimiter_parts(&mut end, &mut quoted, &delimiter.parts) {
+            // Something like a parameter isn't expanded, so the delimiter is just as it was typed
+            end = delimiter.raw.clone();
+            quoted = true;
+        }
+
+        let mut body = String::new();
+        loop {
+            if self.peek().is_none() {
+                return Err(ParseError::IncompleteHereDoc(end));
+            }
+            let mut line = String::new();
+            while let Some(c) = self.peek() {
+                self.position += 1;
+                if c == '\n' {
+                    break;
+                }
+                line.push(c);
+            }
+
+            let line = if strip_tabs {
+                line.trim_start_matches('\t')
+            } else {
+                line.as_str()
+            };
+            if line == end {
+                break;
+            }
+            body.push_str(line);
+            body.push('\n');
+        }
+
+        // The body stands in for the delimiter, so that's the text it came from on the line
+        let raw = delimiter.raw.clone();
+        if quoted {
+            return Ok(Word {
+                raw,
+                parts: vec![WordPart::Quoted(body)],
+            });
+        }
+        let parts = Lexer::new(&body).read_double_quoted(true)?;
+        Ok(Word { raw, parts })
+    }
+
+    fn read_word(&mut self) -> Result<Word, ParseError> {
+        self.read_word_until(true)
+    }
+
+    /// Reads a word, stopping at blanks and operators if it's `delimited`. Words inside of
+    /// expansions like `${VAR:-a b}` aren't delimited and run until the end of the input.
+    fn read_word_until(&mut self, delimited: bool) -> Result<Word, ParseError> {
+        let start = self.position;
+        let mut parts = vec![];
+
+        while let Some(c) = self.peek() {
+            if delimited && self.ends_word(c) {
+                break;
+            }
+            self.position += 1;
+            match c {
+                '\'' => {
+                    let mut quoted = String::new();
+                    loop {
+                        match self.peek() {
+                            Some('\'') => break,
+                            Some(c) => quoted.push(c),
+                            None => return Err(ParseError::Incomplete('\'')),
+                        }
+                        self.position += 1;
+                    }
+                    self.position += 1;
+                    push_part(&mut parts, WordPart::Quoted(quoted));
+                }
+                '"' => {
+                    let quoted = self.read_double_quoted(false)?;
+                    parts.push(WordPart::DoubleQuoted(quoted));
+                }
+                '\\' => match self.peek() {
+                    Some('\n') => self.position += 1,
+                    Some(c) => {
+                        self.position += 1;
+                        push_part(&mut parts, WordPart::Quoted(c.to_string()));
+                    }
+                    None => return Err(ParseError::Incomplete('\\')),
+                },
+                '$' => match self.read_dollar()? {
+                    Some(part) => parts.push(part),
+                    None => push_part(&mut parts, WordPart::Literal(c.to_string())),
+                },
+                '`' => {
+                    let command = self.read_backquoted(false)?;
+                    parts.push(WordPart::CommandSubstitution(command));
+                }
+                _ => push_part(&mut parts, WordPart::Literal(c.to_string())),
+            }
+        }
+
+        Ok(Word {
+            raw: self.chars[start..self.position].iter().collect(),
+            parts,
+        })
+    }
+
+    /// Reads the inside of double quotes. A here-document body reads the same way, except that it
+    /// runs to the end of the input and a `"` is just text.
+    fn read_double_quoted(&mut self, heredoc: bool) -> Result<Vec<WordPart>, ParseError> {
+        let mut parts = vec![];
+
+        loop {
+            let Some(c) = self.peek() else {
+                if heredoc {
+                    break;
+                }
+                return Err(ParseError::Incomplete('"'));
+            };
+            self.position += 1;
+            match c {
+                '"' if !heredoc => break,
+                '\\' => match self.peek() {
+                    Some('\n') => self.position += 1,
+                    Some(escaped @ ('$' | '`' | '\\')) => {
+                        self.position += 1;
+                        push_part(&mut parts, WordPart::Quoted(escaped.to_string()));
+                    }
+                    Some('"') if !heredoc => {
+                        self.position += 1;
+                        push_part(&mut parts, WordPart::Quoted('"'.to_string()));
+                    }
+                    _ => push_part(&mut parts, WordPart::Quoted(c.to_string())),
+                },
+                '$' => match self.read_dollar()? {
+                    Some(part) => parts.push(part),
+                    None => push_part(&mut parts, WordPart::Quoted(c.to_string())),
+                },
+                '`' => {
+                    let command = self.read_backquoted(!heredoc)?;
+                    parts.push(WordPart::CommandSubstitution(command));
+                }
+                _ => push_part(&mut parts, WordPart::Quoted(c.to_string())),
+            }
+        }
+
+        Ok(parts)
+    }
+
+    /// Reads whatever follows a `$`, giving back `None` when the `$` is just a literal dollar sign
+    fn read_dollar(&mut self) -> Result<Option<WordPart>, ParseError> {
+        let name = match self.peek() {
+            Some('{') => {
+                self.position += 1;
+                let end = self.find_closing('}')?;
+                let inner: String = self.chars[self.position..end].iter().collect();
+                self.position = end + 1;
+                return Ok(Some(WordPart::Parameter(parse_braced_parameter(&inner)?)));
+            }
+            Some('(') => {
+                if let Some(expression) = self.read_arithmetic()? {
+                    return Ok(Some(WordPart::Arithmetic(expression)));
+                }
+                self.position += 1;
+                let end = self.find_closing(')')?;
+                let command: String = self.chars[self.position..end].iter().collect();
+                self.position = end + 1;
+                return Ok(Some(WordPart::CommandSubstitution(command)));
+            }
+            Some(c) if is_special_parameter(c) || c.is_ascii_digit() => {
+                self.position += 1;
+                c.to_string()
+            }
+            Some(c) if is_name_start(c) => {
+                let mut name = String::new();
+                while let Some(c) = self.peek().filter(|c| is_name_char(*c)) {
+                    name.push(c);
+                    self.position += 1;
+                }
+                name
+            }
+            _ => return Ok(None),
+        };
+
+        Ok(Some(WordPart::Parameter(Parameter {
+            name,
+            op: ParameterOp::None,
+        })))
+    }
+
+    /// Reads a `((expression))`, as long as the parentheses close with `))`. Otherwise it's a
+    /// subshell or command substitution that happens to start with one, like `((cd x); ls)`.
+    fn read_arithmetic(&mut self) -> Result<Option<Word>, ParseError> {
+        if !self.starts_with("((") {
+            return Ok(None);
+        }
+        let start = self.position;
+        self.position += 2;
+        let end = self.find_closing(')');
+        self.position = start;
+        let Some(end) = end.ok().filter(|end| self.chars.get(end + 1) == Some(&')')) else {
+            return Ok(None);
+        };
+
+        let expression: String = self.chars[start + 2..end].iter().collect();
+        self.position = end + 2;
+        embedded_word(&expression).map(Some)
+    }
+
+    /// Finds the `}` or `)` that closes a `${` or `$(`, skipping over anything quoted or nested
+    /// inside of it
+    fn find_closing(&self, close: char) -> Result<usize, ParseError> {
+        let mut depth = 1;
+        let mut double_quotes = false;
+        let mut i = self.position;
+        // Each `case` being read in a `$(`, with the depth it's at, since a pattern ends in a `)`
+        // that doesn't close anything
+        let mut cases: Vec<(usize, CaseScan)> = vec![];
+
+        while let Some(c) = self.chars.get(i) {
+            let case = cases
+                .last_mut()
+                .filter(|(case_depth, _)| *case_depth == depth);
+            let case = case.map(|(_, scan)| scan);
+            match c {
+                _ if close != ')' || double_quotes => {}
+                // A pattern can start with a `(` of its own
+                '(' if case.as_deref() == Some(&CaseScan::Pattern) => {
+                    i += 1;
+                    continue;
+                }
+                ')' if case.as_deref() == Some(&CaseScan::Pattern) => {
+                    *case.unwrap() = CaseScan::Body;
+                    i += 1;
+                    continue;
+                }
+                ';' if case.is_some() && matches!(self.chars.get(i + 1), Some(';' | '&')) => {
+                    *case.unwrap() = CaseScan::Pattern;
+                    i += 2;
+                    continue;
+                }
+                _ if self.word_at(i, "case") && self.command_starts_at(i) => {
+                    cases.push((depth, CaseScan::Word));
+                    i += 4;
+                    continue;
+                }
+                _ if self.word_at(i, "in") && case.as_deref() == Some(&CaseScan::Word) => {
+                    *case.unwrap() = CaseScan::Pattern;
+                    i += 2;
+                    continue;
+                }
+                _ if self.word_at(i, "esac")
+                    && (case.as_deref() == Some(&CaseScan::Pattern)
+                        || (case.is_some() && self.command_starts_at(i))) =>
+                {
+                    cases.pop();
+                    i += 4;
+                    continue;
+                }
+                _ => {}
+            }
+            match c {
+                '\\' => i += 1,
+                '"' => double_quotes = !double_quotes,
+                '\'' if !double_quotes => {
+                    i += 1;
+                    while self.chars.get(i).is_some_and(|c| *c != '\'') {
+                        i += 1;
+                    }
+                }
+                '{' if close == '}' && !double_quotes && i > 0 && self.chars[i - 1] == '$' => {
+                    depth += 1
+                }
+                '(' if close == ')' && !double_quotes => depth += 1,
+                c if *c == close && !double_quotes => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(i);
+                    }
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+
+        Err(ParseError::Incomplete(close))
+    }
+
+    /// Whether `word` is a whole word starting at `chars[i]`
+    fn word_at(&self, i: usize, word: &str) -> bool {
+        let is_boundary = |c: Option<&char>| {
+            c.is_none_or(|c| is_blank(*c) || *c == '\n' || is_operator_start(*c))
+        };
+        let mut chars = self.chars[i..].iter();
+        word.chars().all(|c| chars.next() == Some(&c))
+            && is_boundary(chars.next())
+            && is_boundary(i.checked_sub(1).map(|i| &self.chars[i]))
+    }
+
+    /// Whether the word at `chars[i]` is where a command starts, so it can be a reserved word
+    fn command_starts_at(&self, i: usize) -> bool {
+        let before: String = self.chars[..i].iter().collect();
+        let before = before.trim_end_matches(is_blank);
+        let Some(last) = before.chars().next_back() else {
+            return true;
+        };
+        if matches!(last, '\n' | ';' | '&' | '|' | '(' | ')' | '{' | '!') {
+            return true;
+        }
+        let previous = before.rsplit(|c: char| c.is_whitespace() || is_operator_start(c));
+        matches!(
+            previous.into_iter().next(),
+            Some("then" | "do" | "else" | "elif" | "if" | "while" | "until")
+        )
+    }
+
+    /// Reads the command inside of backquotes, where a backslash only escapes `$`, `` ` `` and
+    /// `\`, plus `"` when the backquotes are themselves in double quotes
+    fn read_backquoted(&mut self, double_quotes: bool) -> Result<String, ParseError> {
+        let mut command = String::new();
+
+        loop {
+            match self.peek() {
+                None => return Err(ParseError::Incomplete('`')),
+                Some('`') => break,
+                Some('\\') => match self.peek_at(1) {
+                    Some(escaped @ ('$' | '`' | '\\')) => {
+                        command.push(escaped);
+                        self.position += 1;
+                    }
+                    Some('"') if double_quotes => {
+                        command.push('"');
+                        self.position += 1;
+                    }
+                    _ => command.push('\\'),
+                },
+                Some(c) => command.push(c),
+            }
+            self.position += 1;
+        }
+        self.position += 1;
+
+        Ok(command)
+    }
+}
+
+/// Parses the inside of `${...}` into the parameter and what to do with it
+fn parse_braced_parameter(inner: &str) -> Result<Parameter, ParseError> {
+    let bad_substitution = || ParseError::BadSubstitution(format!("${{{inner}}}"));
+
+    if let Some(name) = inner.strip_prefix('#')
+        && is_parameter_name(name)
+    {
+        return Ok(Parameter {
+            name: name.to_string(),
+            op: ParameterOp::Length,
+        });
+    }
+
+    let name_len = match inner.chars().next() {
+        Some(c) if is_special_parameter(c) => 1,
+        Some(c) if c.is_ascii_digit() => inner.chars().take_while(|c| c.is_ascii_digit()).count(),
+        Some(c) if is_name_start(c) => inner.chars().take_while(|c| is_name_char(*c)).count(),
+        _ => return Err(bad_substitution()),
+    };
+    let (mut name, mut rest) = inner.split_at(name_len);
+    // An array element like `${name[1]}`
+    if is_variable_name(name)
+        && rest.starts_with('[')
+        && let Some(end) = rest.find(']')
+    {
+        (name, rest) = inner.split_at(name_len + end + 1);
+    }
+
+    let (colon, rest) = match rest.strip_prefix(':') {
+        Some(rest) => (true, rest),
+        None => (false, rest),
+    };
+    let mut rest_chars = rest.chars();
+    let operator = rest_chars.next();
+    let text = rest_chars.as_str();
+
+    let op = match operator {
+        None if !colon => ParameterOp::None,
+        Some('-') => ParameterOp::Default {
+            colon,
+            word: embedded_word(text)?,
+        },
+        Some('=') => ParameterOp::Assign {
+            colon,
+            word: embedded_word(text)?,
+        },
+        Some('?') => ParameterOp::Error {
+            colon,
+            word: embedded_word(text)?,
+        },
+        Some('+') => ParameterOp::Alternative {
+            colon,
+            word: embedded_word(text)?,
+        },
+        Some('#') if !colon => match text.strip_prefix('#') {
+            Some(pattern) => ParameterOp::RemovePrefix {
+                longest: true,
+                pattern: embedded_word(pattern)?,
+            },
+            None => ParameterOp::RemovePrefix {
+                longest: false,
+                pattern: embedded_word(text)?,
+            },
+        },
+        Some('%') if !colon => match text.strip_prefix('%') {
+            Some(pattern) => ParameterOp::RemoveSuffix {
+                longest: true,
+                pattern: embedded_word(pattern)?,
+            },
+            None => ParameterOp::RemoveSuffix {
+                longest: false,
+                pattern: embedded_word(text)?,
+            },
+        },
+        _ => return Err(bad_substitution()),
+    };
+
+    Ok(Parameter {
+        name: name.to_string(),
+        op,
+    })
+}
+
+/// Lexes a word that lives inside of an expansion, where blanks and operators are just text
+fn embedded_word(text: &str) -> Result<Word, ParseError> {
+    Lexer::new(text).read_word_until(false)
+}
+
+/// Adds a here-document delimiter's text after quote removal, noting whether any of it was quoted.
+/// Gives false for a part that would be expanded anywhere else, which the delimiter can't take
+/// apart.
+fn push_delimiter_parts(end: &mut String, quoted: &mut bool, parts: &[WordPart]) -> bool {
+    for part in parts {
+        match part {
+            WordPart::Literal(text) => end.push_str(text),
+            WordPart::Quoted(text) => {
+                *quoted = true;
+                end.push_str(text);
+            }
+            WordPart::DoubleQuoted(inner) => {
+                *quoted = true;
+                if !push_delimiter_parts(end, quoted, inner) {
+                    return false;
+                }
+            }
+            _ => return false,
+        }
+    }
+    true
+}
+
+/// Pushes a part onto a word, merging it into the previous part when they are the same kind of text
+fn push_part(parts: &mut Vec<WordPart>, part: WordPart) {
+    match (parts.last_mut(), part) {
+        (Some(WordPart::Literal(previous)), WordPart::Literal(text))
+        | (Some(WordPart::Quoted(previous)), WordPart::Quoted(text)) => previous.push_str(&text),
+        (_, part) => parts.push(part),
+    }
+}
+
+pub fn tokenize(input: &str) -> Result<Vec<Token>, ParseError> {
+    Lexer::new(input).tokenize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What each token is, as a string that's easy to compare
+    fn describe(input: &str) -> Vec<String> {
+        let tokens = tokenize(input).unwrap();
+        tokens
+            .iter()
+            .map(|token| match token {
+                Token::Word(word) => format!("word {}", word.raw),
+                Token::IoNumber(n) => format!("io {n}"),
+                Token::Operator(operator) => format!("op {}", operator.as_str()),
+                Token::Newline => "newline".to_string(),
+                Token::HereDoc(body) => format!("heredoc {:?}", body.raw),
+                Token::Arithmetic(expression) => format!("arith {}", expression.raw),
+            })
+            .collect()
+    }
+
+    fn word(input: &str) -> Word {
+        match tokenize(input).unwrap().as_slice() {
+            [Token::Word(word)] => word.clone(),
+            tokens => panic!("expected one word, got {tokens:?}"),
+        }
+    }
+
+    #[test]
+    fn operators() {
+        assert_eq!(
+            describe("a&&b||c;d|e&"),
+            [
+                "word a", "op &&", "word b", "op ||", "word c", "op ;", "word d", "op |", "word e",
+                "op &"
+            ]
+        );
+        assert_eq!(
+            describe("x>f>>g<h 2>&1 &>>i <<<j"),
+            [
+                "word x", "op >", "word f", "op >>", "word g", "op <", "word h", "io 2", "op >&",
+                "word 1", "op &>>", "word i", "op <<<", "word j"
+            ]
+        );
+        assert_eq!(
+            describe("a ;; b\n"),
+            ["word a", "op ;;", "word b", "newline"]
+        );
+    }
+
+    #[test]
+    fn quotes() {
+        assert_eq!(word("'a b'").parts, [WordPart::Quoted("a b".to_string())]);
+        assert_eq!(
+            word("x\"y\\\"z\"").parts,
+            [
+                WordPart::Literal("x".to_string()),
+                WordPart::DoubleQuoted(vec![WordPart::Quoted("y\"z".to_string())])
+            ]
+        );
+        assert_eq!(
+            word("a\\ b").parts,
+            [
+                WordPart::Literal("a".to_string()),
+                WordPart::Quoted(" ".to_string()),
+                WordPart::Literal("b".to_string())
+            ]
+        );
+        assert_eq!(describe("'a;b' \"c|d\""), ["word 'a;b'", "word \"c|d\""]);
+        assert!(matches!(
+            tokenize("'open"),
+            Err(ParseError::Incomplete('\''))
+        ));
+        assert!(matches!(
+            tokenize("\"open"),
+            Err(ParseError::Incomplete('"'))
+        ));
+    }
+
+    #[test]
+    fn substitutions() {
+        assert_eq!(
+            word("$(echo a)").parts,
+            [WordPart::CommandSubstitution("echo a".to_string())]
+        );
+        assert_eq!(
+            word("`echo a`").parts,
+            [WordPart::CommandSubstitution("echo a".to_string())]
+        );
+        assert_eq!(
+            word("$(echo $(echo a) \")\")").parts,
+            [WordPart::CommandSubstitution(
+                "echo $(echo a) \")\"".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn case_in_substitution() {
+        let command = |input: &str| match word(input).parts.as_slice() {
+            [WordPart::CommandSubstitution(command)] => command.clone(),
+            parts => panic!("expected a command substitution, got {parts:?}"),
+        };
+        assert_eq!(
+            command("$(case a in a) echo yes;; esac)"),
+            "case a in a) echo yes;; esac"
+        );
+        assert_eq!(
+            command("$(case a in (a) echo $(case b in b) echo no;; esac);; esac)"),
+            "case a in (a) echo $(case b in b) echo no;; esac);; esac"
+        );
+        assert_eq!(
+            command("$(if true; then case a in a) x; esac; fi)"),
+            "if true; then case a in a) x; esac; fi"
+        );
+        // Only a `case` where a command starts opens one
+        assert_eq!(command("$(echo case in x)"), "echo case in x");
+    }
+
+    #[test]
+    fn heredoc_delimiters() {
+        let body = |input: &str| {
+            let tokens = tokenize(input).unwrap();
+            match tokens
+                .iter()
+                .find(|token| matches!(token, Token::HereDoc(_)))
+            {
+                Some(Token::HereDoc(body)) => body.parts.clone(),
+                _ => panic!("expected a here-document in {tokens:?}"),
+            }
+        };
+        // Unquoted delimiters give a body that's expanded
+        assert!(matches!(
+            body("cat <<EOF\n$x\nEOF\n").as_slice(),
+            [WordPart::Parameter(_), ..]
+        ));
+        // Any quoting means the body is taken literally
+        for input in [
+            "cat <<'EOF'\n$x\nEOF\n",
+            "cat <<\"EOF\"\n$x\nEOF\n",
+            "cat <<E\"O\"F\n$x\nEOF\n",
+            "cat <<\\EOF\n$x\nEOF\n",
+        ] {
+            assert_eq!(
+                body(input),
+                [WordPart::Quoted("$x\n".to_string())],
+                "{input}"
+            );
+        }
+        assert_eq!(
+            body("cat <<-EOF\n\tx\n\tEOF\n"),
+            [WordPart::Quoted("x\n".to_string())]
+        );
+        assert!(matches!(
+            tokenize("cat <<EOF\nno end\n"),
+            Err(ParseError::IncompleteHereDoc(_))
+        ));
+    }
+}
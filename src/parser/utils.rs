@@ -0,0 +1,622 @@
+use std::collections::BTreeMap;
+
+use thiserror::Error;
+
+use crate::builtins::test::{BINARY_OPERATORS, UNARY_OPERATORS};
+use crate::parser::ast::{
+    AndOr, Assignment, CaseItem, Command, CompoundCommand, Conditional, Connector, List, Pipeline,
+    RedirectKind, Redirection, SimpleCommand, Word, WordPart,
+};
+use crate::parser::lexer::{Operator, Token, is_variable_name, tokenize};
+
+#[derive(Debug, Error)]
+pub enum ParseError {
+    #[error("syntax error near unexpected token `{0}'")]
+    UnexpectedToken(String),
+    #[error("syntax error: unexpected end of file")]
+    UnexpectedEof,
+    #[error("unexpected EOF while looking for matching `{0}'")]
+    Incomplete(char),
+    #[error("{0}: bad substitution")]
+    BadSubstitution(String),
+    #[error("here-document delimited by end-of-file (wanted `{0}')")]
+    IncompleteHereDoc(String),
+}
+
+impl ParseError {
+    /// Whether the input could still become valid with more lines, like an unclosed quote
+    pub fn is_incomplete(&self) -> bool {
+        matches!(
+            self,
+            ParseError::UnexpectedEof
+                | ParseError::Incomplete(_)
+                | ParseError::IncompleteHereDoc(_)
+        )
+    }
+}
+
+pub struct Parser<'a> {
+    tokens: Vec<Token>,
+    position: usize,
+    /// Aliases to expand where they start a command
+    aliases: &'a BTreeMap<String, String>,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(tokens: Vec<Token>, aliases: &'a BTreeMap<String, String>) -> Parser<'a> {
+        Parser {
+            tokens,
+            position: 0,
+            aliases,
+        }
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<&Token> {
+        self.tokens.get(self.position + offset)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn unexpected(&self) -> ParseError {
+        match self.peek() {
+            Some(token) => ParseError::UnexpectedToken(token.to_string()),
+            None => ParseError::UnexpectedEof,
+        }
+    }
+
+    fn peek_operator(&self, operator: Operator) -> bool {
+        self.peek() == Some(&Token::Operator(operator))
+    }
+
+    fn peek_reserved(&self, reserved: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(word)) if word.is_reserved(reserved))
+    }
+
+    fn expect_reserved(&mut self, reserved: &str) -> Result<(), ParseError> {
+        if self.peek_reserved(reserved) {
+            self.position += 1;
+            Ok(())
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    /// Replaces the word at `at` with the tokens of its value if it's an unquoted alias. The value
+    /// can start with another alias, apart from ones in `expanding` which are already being
+    /// expanded, so that `alias ls='ls -F'` doesn't go on forever. A value ending in a blank
+    /// means the word after it gets checked too.
+    fn expand_alias(&mut self, at: usize, expanding: &mut Vec<String>) -> Result<(), ParseError> {
+        let Some(Token::Word(word)) = self.tokens.get(at) else {
+            return Ok(());
+        };
+        let Some(name) = word.as_unquoted() else {
+            return Ok(());
+        };
+        if expanding.iter().any(|expanded| expanded == name) {
+            return Ok(());
+        }
+        let Some(value) = self.aliases.get(name) else {
+            return Ok(());
+        };
+
+        expanding.push(name.to_string());
+        let tokens = tokenize(value)?;
+        let mut end = at + tokens.len();
+        self.tokens.splice(at..=at, tokens);
+
+        let length = self.tokens.len();
+        self.expand_alias(at, expanding)?;
+        end = end + self.tokens.len() - length;
+        if value.ends_with([' ', '\t']) {
+            self.expand_alias(end, &mut vec![])?;
+        }
+        Ok(())
+    }
+
+    fn skip_newlines(&mut self) {
+        while self.peek() == Some(&Token::Newline) {
+            self.position += 1;
+        }
+    }
+
+    /// Whether the next token closes the list we're currently in
+    fn at_list_end(&self) -> bool {
+        const LIST_ENDS: [&str; 8] = ["}", "then", "elif", "else", "fi", "do", "done", "esac"];
+        match self.peek() {
+            None => true,
+            Some(Token::Operator(Operator::RParen | Operator::DSemi)) => true,
+            Some(Token::Word(word)) => LIST_ENDS.iter().any(|end| word.is_reserved(end)),
+            _ => false,
+        }
+    }
+
+    /// Parses a list that has to have at least one command in it, like the condition of an `if`
+    fn parse_compound_list(&mut self) -> Result<List, ParseError> {
+        let list = self.parse_list()?;
+        if list.items.is_empty() {
+            return Err(self.unexpected());
+        }
+        Ok(list)
+    }
+
+    /// Takes a `;` or newlines, which is what comes before reserved words like `do`
+    fn skip_separator(&mut self) {
+        if self.peek_operator(Operator::Semi) {
+            self.position += 1;
+        }
+        self.skip_newlines();
+    }
+
+    pub fn parse_program(&mut self) -> Result<List, ParseError> {
+        let list = self.parse_list()?;
+        if self.peek().is_some() {
+            return Err(self.unexpected());
+        }
+        Ok(list)
+    }
+
+    fn parse_list(&mut self) -> Result<List, ParseError> {
+        let mut items = vec![];
+
+        self.skip_newlines();
+        while !self.at_list_end() {
+            let mut and_or = self.parse_and_or()?;
+            match self.peek() {
+                Some(Token::Operator(Operator::Amp)) => {
+                    and_or.background = true;
+                    self.position += 1;
+                    self.skip_newlines();
+                }
+                Some(Token::Operator(Operator::Semi)) => {
+                    self.position += 1;
+                    self.skip_newlines();
+                }
+                Some(Token::Newline) => self.skip_newlines(),
+                _ if self.at_list_end() => {}
+                _ => return Err(self.unexpected()),
+            }
+            items.push(and_or);
+        }
+
+        Ok(List { items })
+    }
+
+    fn parse_and_or(&mut self) -> Result<AndOr, ParseError> {
+        let first = self.parse_pipeline()?;
+        let mut rest = vec![];
+
+        loop {
+            let connector = match self.peek() {
+                Some(Token::Operator(Operator::AndIf)) => Connector::And,
+                Some(Token::Operator(Operator::OrIf)) => Connector::Or,
+                _ => break,
+            };
+            self.position += 1;
+            self.skip_newlines();
+            rest.push((connector, self.parse_pipeline()?));
+        }
+
+        Ok(AndOr {
+            first,
+            rest,
+            background: false,
+        })
+    }
+
+    fn parse_pipeline(&mut self) -> Result<Pipeline, ParseError> {
+        let negated = self.peek_reserved("!");
+        if negated {
+            self.position += 1;
+        }
+        let mut commands = vec![self.parse_command()?];
+        while self.peek_operator(Operator::Pipe) {
+            self.position += 1;
+            self.skip_newlines();
+            commands.push(self.parse_command()?);
+        }
+        Ok(Pipeline { negated, commands })
+    }
+
+    fn parse_command(&mut self) -> Result<Command, ParseError> {
+        self.expand_alias(self.position, &mut vec![])?;
+        let parens = [
+            self.peek_at(1) == Some(&Token::Operator(Operator::LParen)),
+            self.peek_at(2) == Some(&Token::Operator(Operator::RParen)),
+        ];
+        let is_word = matches!(self.peek(), Some(Token::Word(_)));
+        if self.peek_reserved("function") || (is_word && parens == [true, true]) {
+            return self.parse_function();
+        }
+
+        let compound = if self.peek_reserved("{") {
+            self.position += 1;
+            let list = self.parse_list()?;
+            self.expect_reserved("}")?;
+            Some(CompoundCommand::BraceGroup(list))
+        } else if self.peek_operator(Operator::LParen) {
+            self.position += 1;
+            let list = self.parse_list()?;
+            if !self.peek_operator(Operator::RParen) {
+                return Err(self.unexpected());
+            }
+            self.position += 1;
+            Some(CompoundCommand::Subshell(list))
+        } else if self.peek_reserved("if") {
+            Some(self.parse_if()?)
+        } else if self.peek_reserved("while") || self.peek_reserved("until") {
+            Some(self.parse_while()?)
+        } else if self.peek_reserved("for") {
+            Some(self.parse_for()?)
+        } else if self.peek_reserved("case") {
+            Some(self.parse_case()?)
+        } else if let Some(Token::Arithmetic(expression)) = self.peek() {
+            let expression = expression.clone();
+            self.position += 1;
+            Some(CompoundCommand::Arithmetic(expression))
+        } else if self.peek_reserved("[[") {
+            self.position += 1;
+            let expression = self.parse_conditional_or()?;
+            self.skip_newlines();
+            self.expect_reserved("]]")?;
+            Some(CompoundCommand::Conditional(expression))
+        } else {
+            None
+        };
+
+        match compound {
+            Some(compound) => {
+                let mut redirections = vec![];
+                while let Some(redirection) = self.parse_redirect()? {
+                    redirections.push(redirection);
+                }
+                Ok(Command::Compound(compound, redirections))
+            }
+            None => Ok(Command::Simple(self.parse_simple_command()?)),
+        }
+    }
+
+    /// Parses `name() body`, or bash's `function name body` where the parentheses are optional
+    fn parse_function(&mut self) -> Result<Command, ParseError> {
+        if self.peek_reserved("function") {
+            self.position += 1;
+        }
+        let name = match self.next() {
+            Some(Token::Word(word)) => match word.as_unquoted() {
+                Some(name) if !name.contains('=') => name.to_string(),
+                _ => return Err(ParseError::UnexpectedToken(word.raw)),
+            },
+            Some(token) => return Err(ParseError::UnexpectedToken(token.to_string())),
+            None => return Err(ParseError::UnexpectedEof),
+        };
+        if self.peek_operator(Operator::LParen) {
+            self.position += 1;
+            if !self.peek_operator(Operator::RParen) {
+                return Err(self.unexpected());
+            }
+            self.position += 1;
+        }
+        self.skip_newlines();
+
+        let body = self.parse_command()?;
+        if !matches!(body, Command::Compound(..)) {
+            return Err(ParseError::UnexpectedToken(body.to_string()));
+        }
+        Ok(Command::Function {
+            name,
+            body: Box::new(body),
+        })
+    }
+
+    fn parse_if(&mut self) -> Result<CompoundCommand, ParseError> {
+        self.expect_reserved("if")?;
+        let mut branches = vec![];
+        let mut otherwise = None;
+        loop {
+            let condition = self.parse_compound_list()?;
+            self.expect_reserved("then")?;
+            branches.push((condition, self.parse_compound_list()?));
+
+            if self.peek_reserved("elif") {
+                self.position += 1;
+                continue;
+            }
+            if self.peek_reserved("else") {
+                self.position += 1;
+                otherwise = Some(self.parse_compound_list()?);
+            }
+            self.expect_reserved("fi")?;
+            break;
+        }
+        Ok(CompoundCommand::If {
+            branches,
+            otherwise,
+        })
+    }
+
+    fn parse_while(&mut self) -> Result<CompoundCommand, ParseError> {
+        let until = self.peek_reserved("until");
+        self.position += 1;
+        let condition = self.parse_compound_list()?;
+        let body = self.parse_do_group()?;
+        Ok(CompoundCommand::While {
+            until,
+            condition,
+            body,
+        })
+    }
+
+    /// Parses the `do ... done` body of a loop
+    fn parse_do_group(&mut self) -> Result<List, ParseError> {
+        self.expect_reserved("do")?;
+        let body = self.parse_compound_list()?;
+        self.expect_reserved("done")?;
+        Ok(body)
+    }
+
+    fn parse_for(&mut self) -> Result<CompoundCommand, ParseError> {
+        self.expect_reserved("for")?;
+        let name = match self.next() {
+            Some(Token::Word(word)) if is_variable_name(&word.raw) => word.raw,
+            Some(token) => return Err(ParseError::UnexpectedToken(token.to_string())),
+            None => return Err(ParseError::UnexpectedEof),
+        };
+
+        self.skip_newlines();
+        let mut words = None;
+        if self.peek_reserved("in") {
+            self.position += 1;
+            let mut list = vec![];
+            while let Some(Token::Word(word)) = self.peek() {
+                list.push(word.clone());
+                self.position += 1;
+            }
+            words = Some(list);
+        }
+        self.skip_separator();
+
+        let body = self.parse_do_group()?;
+        Ok(CompoundCommand::For { name, words, body })
+    }
+
+    fn parse_case(&mut self) -> Result<CompoundCommand, ParseError> {
+        self.expect_reserved("case")?;
+        let word = match self.next() {
+            Some(Token::Word(word)) => word,
+            Some(token) => return Err(ParseError::UnexpectedToken(token.to_string())),
+            None => return Err(ParseError::UnexpectedEof),
+        };
+        self.skip_newlines();
+        self.expect_reserved("in")?;
+        self.skip_newlines();
+
+        let mut items = vec![];
+        while !self.peek_reserved("esac") {
+            if self.peek_operator(Operator::LParen) {
+                self.position += 1;
+            }
+            let mut patterns = vec![];
+            loop {
+                match self.next() {
+                    Some(Token::Word(pattern)) => patterns.push(pattern),
+                    Some(token) => return Err(ParseError::UnexpectedToken(token.to_string())),
+                    None => return Err(ParseError::UnexpectedEof),
+                }
+                if !self.peek_operator(Operator::Pipe) {
+                    break;
+                }
+                self.position += 1;
+            }
+            if !self.peek_operator(Operator::RParen) {
+                return Err(self.unexpected());
+            }
+            self.position += 1;
+
+            let body = self.parse_list()?;
+            items.push(CaseItem { patterns, body });
+            // The last item doesn't need its `;;`
+            if !self.peek_operator(Operator::DSemi) {
+                break;
+            }
+            self.position += 1;
+            self.skip_newlines();
+        }
+        self.expect_reserved("esac")?;
+        Ok(CompoundCommand::Case { word, items })
+    }
+
+    /// Takes the next word inside `[[ ]]`, where newlines don't matter
+    fn next_conditional_word(&mut self) -> Result<Word, ParseError> {
+        self.skip_newlines();
+        match self.next() {
+            Some(Token::Word(word)) if !word.is_reserved("]]") => Ok(word),
+            Some(token) => Err(ParseError::UnexpectedToken(token.to_string())),
+            None => Err(ParseError::UnexpectedEof),
+        }
+    }
+
+    fn parse_conditional_or(&mut self) -> Result<Conditional, ParseError> {
+        let mut expression = self.parse_conditional_and()?;
+        self.skip_newlines();
+        while self.peek_operator(Operator::OrIf) {
+            self.position += 1;
+            let right = self.parse_conditional_and()?;
+            expression = Conditional::Or(Box::new(expression), Box::new(right));
+            self.skip_newlines();
+        }
+        Ok(expression)
+    }
+
+    fn parse_conditional_and(&mut self) -> Result<Conditional, ParseError> {
+        let mut expression = self.parse_conditional_not()?;
+        self.skip_newlines();
+        while self.peek_operator(Operator::AndIf) {
+            self.position += 1;
+            let right = self.parse_conditional_not()?;
+            expression = Conditional::And(Box::new(expression), Box::new(right));
+            self.skip_newlines();
+        }
+        Ok(expression)
+    }
+
+    fn parse_conditional_not(&mut self) -> Result<Conditional, ParseError> {
+        self.skip_newlines();
+        if self.peek_reserved("!") {
+            self.position += 1;
+            let expression = self.parse_conditional_not()?;
+            return Ok(Conditional::Not(Box::new(expression)));
+        }
+        if self.peek_operator(Operator::LParen) {
+            self.position += 1;
+            let expression = self.parse_conditional_or()?;
+            if !self.peek_operator(Operator::RParen) {
+                return Err(self.unexpected());
+            }
+            self.position += 1;
+            return Ok(expression);
+        }
+
+        let word = self.next_conditional_word()?;
+        if let Some(op) = word.as_unquoted()
+            && UNARY_OPERATORS.contains(&op)
+        {
+            let operand = self.next_conditional_word()?;
+            return Ok(Conditional::Unary(op.to_string(), operand));
+        }
+        // `<` and `>` compare strings here rather than redirecting
+        let op = match self.peek() {
+            Some(Token::Operator(Operator::Less)) => "<",
+            Some(Token::Operator(Operator::Great)) => ">",
+            Some(Token::Word(op)) => match op.as_unquoted() {
+                Some(op) if op == "=~" || BINARY_OPERATORS.contains(&op) => op,
+                _ => return Ok(Conditional::Word(word)),
+            },
+            _ => return Ok(Conditional::Word(word)),
+        }
+        .to_string();
+        self.position += 1;
+        let right = self.next_conditional_word()?;
+        Ok(Conditional::Binary(op, word, right))
+    }
+
+    fn parse_simple_command(&mut self) -> Result<SimpleCommand, ParseError> {
+        let mut assignments = vec![];
+        let mut words = vec![];
+        let mut redirections = vec![];
+
+        loop {
+            if let Some(redirection) = self.parse_redirect()? {
+                redirections.push(redirection);
+                continue;
+            }
+            match self.peek() {
+                Some(Token::Word(word)) => {
+                    // Assignments only count before the command name
+                    match split_assignment(word).filter(|_| words.is_empty()) {
+                        Some(assignment) => {
+                            assignments.push(assignment);
+                            self.position += 1;
+                            // The command name can still be an alias after its assignments
+                            self.expand_alias(self.position, &mut vec![])?;
+                        }
+                        None => {
+                            words.push(word.clone());
+                            self.position += 1;
+                        }
+                    }
+                }
+                _ => break,
+            }
+        }
+
+        if assignments.is_empty() && words.is_empty() && redirections.is_empty() {
+            return Err(self.unexpected());
+        }
+        Ok(SimpleCommand {
+            assignments,
+            words,
+            redirections,
+        })
+    }
+
+    fn parse_redirect(&mut self) -> Result<Option<Redirection>, ParseError> {
+        let fd = match self.peek() {
+            Some(Token::IoNumber(n)) => {
+                let n = *n;
+                self.position += 1;
+                Some(n)
+            }
+            _ => None,
+        };
+
+        let (kind, default_fd) = match self.peek() {
+            Some(Token::Operator(Operator::Great | Operator::Clobber)) => (RedirectKind::Output, 1),
+            Some(Token::Operator(Operator::DGreat)) => (RedirectKind::Append, 1),
+            Some(Token::Operator(Operator::Less)) => (RedirectKind::Input, 0),
+            Some(Token::Operator(Operator::LessGreat)) => (RedirectKind::ReadWrite, 0),
+            Some(Token::Operator(Operator::DLess | Operator::DLessDash)) => {
+                (RedirectKind::HereDoc, 0)
+            }
+            Some(Token::Operator(Operator::TLess)) => (RedirectKind::HereString, 0),
+            Some(Token::Operator(Operator::GreatAnd)) => (RedirectKind::DupOutput, 1),
+            Some(Token::Operator(Operator::LessAnd)) => (RedirectKind::DupInput, 0),
+            Some(Token::Operator(Operator::AndGreat)) => (RedirectKind::OutputAll, 1),
+            Some(Token::Operator(Operator::AndDGreat)) => (RedirectKind::AppendAll, 1),
+            _ => {
+                if fd.is_some() {
+                    return Err(self.unexpected());
+                }
+                return Ok(None);
+            }
+        };
+        self.position += 1;
+
+        let target = match (self.next(), &kind) {
+            (Some(Token::HereDoc(body)), RedirectKind::HereDoc) => body,
+            (Some(Token::Word(target)), kind) if *kind != RedirectKind::HereDoc => target,
+            (Some(token), _) => return Err(ParseError::UnexpectedToken(token.to_string())),
+            (None, _) => return Err(ParseError::UnexpectedToken("newline".to_string())),
+        };
+        Ok(Some(Redirection {
+            fd: fd.unwrap_or(default_fd),
+            kind,
+            target,
+        }))
+    }
+}
+
+/// Splits a `NAME=value` word into an assignment, as long as the name isn't quoted
+fn split_assignment(word: &Word) -> Option<Assignment> {
+    let Some(WordPart::Literal(literal)) = word.parts.first() else {
+        return None;
+    };
+    let (name, value) = literal.split_once('=')?;
+    if !is_variable_name(name) {
+        return None;
+    }
+
+    let mut parts = vec![];
+    if !value.is_empty() {
+        parts.push(WordPart::Literal(value.to_string()));
+    }
+    parts.extend(word.parts[1..].iter().cloned());
+    let raw = word.raw[name.len() + 1..].to_string();
+
+    Some(Assignment {
+        name: name.to_string(),
+        value: Word { raw, parts },
+    })
+}
+
+/// Parses commands, expanding any of the `aliases` that start one
+pub fn parse_input(input: &str, aliases: &BTreeMap<String, String>) -> Result<List, ParseError> {
+    let tokens = tokenize(input)?;
+    Parser::new(tokens, aliases).parse_program()
+}
@@ -0,0 +1,360 @@
+use std::fmt;
+use std::mem;
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+use std::thread::JoinHandle;
+
+use crate::subprocesses::utils::exit_code;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum State {
+    Running,
+    Stopped,
+    Done(i32),
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            State::Running => write!(f, "Running"),
+            State::Stopped => write!(f, "Stopped"),
+            State::Done(0) => write!(f, "Done"),
+            State::Done(status) => write!(f, "Exit {status}"),
+        }
+    }
+}
+
+/// One of the commands making up a job
+pub enum Member {
+    Process {
+        pid: libc::pid_t,
+        state: State,
+    },
+    /// A builtin running on one of the shell's threads, which can't be stopped
+    Thread(JoinHandle<i32>),
+    /// A command that had already finished by the time the job started
+    Done(i32),
+}
+
+impl Member {
+    fn state(&self) -> State {
+        match self {
+            Member::Process { state, .. } => *state,
+            Member::Thread(_) => State::Running,
+            Member::Done(status) => State::Done(*status),
+        }
+    }
+
+    fn join(&mut self) {
+        if let Member::Thread(_) = self {
+            let Member::Thread(handle) = mem::replace(self, Member::Done(0)) else {
+                unreachable!();
+            };
+            // A builtin that panicked has failed like any other
+            *self = Member::Done(handle.join().unwrap_or(1));
+        }
+    }
+}
+
+/// Turns what `waitpid` said about a process into its state
+fn wait_state(status: i32) -> State {
+    if libc::WIFSTOPPED(status) {
+        State::Stopped
+    } else if libc::WIFCONTINUED(status) {
+        State::Running
+    } else {
+        State::Done(exit_code(ExitStatus::from_raw(status)))
+    }
+}
+
+/// A pipeline the shell keeps track of while it runs
+pub struct Job {
+    /// Given out when the job goes in the table
+    pub id: usize,
+    /// The process group the job runs in, or 0 without job control
+    pub pgid: libc::pid_t,
+    pub command: String,
+    pub members: Vec<Member>,
+    /// The state the user was last told about, so each change is only reported once
+    pub reported: State,
+    /// Started with `!`, so its status is turned round once it's done
+    pub negated: bool,
+}
+
+impl Job {
+    pub fn new(pgid: libc::pid_t, command: String, members: Vec<Member>) -> Job {
+        Job {
+            id: 0,
+            pgid,
+            command,
+            members,
+            reported: State::Running,
+            negated: false,
+        }
+    }
+
+    /// The job is stopped if any of it is, and done once all of it is, with the status of its
+    /// last command
+    pub fn state(&self) -> State {
+        let states: Vec<State> = self.members.iter().map(Member::state).collect();
+        if states.contains(&State::Stopped) {
+            State::Stopped
+        } else if states.contains(&State::Running) {
+            State::Running
+        } else {
+            match states.last().copied().unwrap_or(State::Done(0)) {
+                State::Done(status) if self.negated => State::Done((status == 0) as i32),
+                state => state,
+            }
+        }
+    }
+
+    /// The status `$?` gets from the job, where being stopped counts as being hit by SIGTSTP
+    pub fn status(&self) -> i32 {
+        match self.state() {
+            State::Done(status) => status,
+            State::Stopped => 128 + libc::SIGTSTP,
+            State::Running => 0,
+        }
+    }
+
+    pub fn pids(&self) -> Vec<libc::pid_t> {
+        self.members
+            .iter()
+            .filter_map(|member| match member {
+                Member::Process { pid, .. } => Some(*pid),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Blocks until the job has finished or one of its processes has stopped
+    pub fn wait(&mut self) -> State {
+        for member in &mut self.members {
+            let Member::Process { pid, state } = member else {
+                continue;
+            };
+            if *state != State::Running {
+                continue;
+            }
+
+            let mut status = 0;
+            if unsafe { libc::waitpid(*pid, &mut status, libc::WUNTRACED) } == -1 {
+                // Someone else already waited for it, so there's nothing left to know
+                *state = State::Done(0);
+                continue;
+            }
+            *state = wait_state(status);
+            if *state == State::Stopped {
+                // The rest of the job was stopped along with it, so take in their news too
+                self.poll();
+                return State::Stopped;
+            }
+        }
+
+        self.members.iter_mut().for_each(Member::join);
+        self.state()
+    }
+
+    /// Catches up on anything that's happened to the job without blocking
+    pub fn poll(&mut self) {
+        for member in &mut self.members {
+            match member {
+                Member::Process { pid, state } if !matches!(state, State::Done(_)) => {
+                    let mut status = 0;
+                    let options = libc::WNOHANG | libc::WUNTRACED | libc::WCONTINUED;
+                    match unsafe { libc::waitpid(*pid, &mut status, options) } {
+                        0 => {}
+                        -1 => *state = State::Done(0),
+                        _ => *state = wait_state(status),
+                    }
+                }
+                Member::Thread(handle) if handle.is_finished() => member.join(),
+                _ => {}
+            }
+        }
+    }
+
+    /// Sends SIGCONT to every process in the job so that a stopped job carries on
+    pub fn resume(&mut self) {
+        if self.pgid != 0 {
+            unsafe { libc::kill(-self.pgid, libc::SIGCONT) };
+        }
+        for member in &mut self.members {
+            if let Member::Process { pid, state } = member
+                && *state == State::Stopped
+            {
+                if self.pgid == 0 {
+                    unsafe { libc::kill(*pid, libc::SIGCONT) };
+                }
+                *state = State::Running;
+            }
+        }
+        self.reported = State::Running;
+    }
+}
+
+/// The jobs the shell is keeping track of, with the most recently used one last
+#[derive(Default)]
+pub struct Jobs {
+    jobs: Vec<Job>,
+    /// Set for a copy of the table in a subshell, which can list the jobs but can't wait for
+    /// them since they aren't its children
+    snapshot: bool,
+}
+
+impl Clone for Jobs {
+    fn clone(&self) -> Jobs {
+        let jobs = self
+            .jobs
+            .iter()
+            .map(|job| {
+                let members = job
+                    .members
+                    .iter()
+                    .map(|member| match member {
+                        Member::Process { pid, state } => Member::Process {
+                            pid: *pid,
+                            state: *state,
+                        },
+                        // Threads can't be shared, but all that matters is the job's processes
+                        Member::Thread(_) | Member::Done(_) => Member::Done(0),
+                    })
+                    .collect();
+                Job {
+                    id: job.id,
+                    pgid: job.pgid,
+                    command: job.command.clone(),
+                    members,
+                    reported: job.reported,
+                    negated: job.negated,
+                }
+            })
+            .collect();
+        Jobs {
+            jobs,
+            snapshot: true,
+        }
+    }
+}
+
+impl Jobs {
+    /// Puts a job in the table, giving it the next free number if it doesn't already have one,
+    /// and makes it the current job
+    pub fn insert(&mut self, mut job: Job) -> usize {
+        if job.id == 0 {
+            job.id = self.jobs.iter().map(|job| job.id).max().unwrap_or(0) + 1;
+        }
+        let id = job.id;
+        self.jobs.push(job);
+        id
+    }
+
+    pub fn remove(&mut self, id: usize) -> Option<Job> {
+        let index = self.jobs.iter().position(|job| job.id == id)?;
+        Some(self.jobs.remove(index))
+    }
+
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut Job> {
+        self.jobs.iter_mut().find(|job| job.id == id)
+    }
+
+    pub fn ids(&self) -> Vec<usize> {
+        let mut ids: Vec<usize> = self.jobs.iter().map(|job| job.id).collect();
+        ids.sort();
+        ids
+    }
+
+    /// Finds the job a spec like `%2`, `%%`, `%-` or `%sleep` refers to, where no spec at all
+    /// means the current job
+    pub fn find(&self, spec: Option<&str>) -> Result<usize, String> {
+        let no_such_job = || format!("{}: no such job", spec.unwrap_or("current"));
+        let spec = spec.map(|spec| spec.strip_prefix('%').unwrap_or(spec));
+        let job = match spec {
+            None | Some("") | Some("%") | Some("+") => self.jobs.last(),
+            Some("-") => self.jobs.iter().rev().nth(1),
+            Some(number) if number.chars().all(|c| c.is_ascii_digit()) => {
+                let id = number.parse::<usize>().map_err(|_| no_such_job())?;
+                self.jobs.iter().find(|job| job.id == id)
+            }
+            Some(prefix) => self
+                .jobs
+                .iter()
+                .rev()
+                .find(|job| job.command.starts_with(prefix)),
+        };
+        job.map(|job| job.id).ok_or_else(no_such_job)
+    }
+
+    /// Finds the job a process belongs to
+    pub fn find_pid(&self, pid: libc::pid_t) -> Option<usize> {
+        self.jobs
+            .iter()
+            .find(|job| job.pids().contains(&pid))
+            .map(|job| job.id)
+    }
+
+    /// `+` for the current job and `-` for the one before it, which `%+` and `%-` refer to
+    pub fn marker(&self, id: usize) -> char {
+        let position = self.jobs.iter().rev().position(|job| job.id == id);
+        match position {
+            Some(0) => '+',
+            Some(1) => '-',
+            _ => ' ',
+        }
+    }
+
+    /// Describes a job the way `jobs` lists it, like `[1]+  Running    sleep 10 &`
+    pub fn describe(&self, id: usize, with_pids: bool) -> String {
+        let Some(job) = self.jobs.iter().find(|job| job.id == id) else {
+            return String::new();
+        };
+        let marker = self.marker(id);
+
+        let state = job.state();
+        let background = if state == State::Running { " &" } else { "" };
+        let pids = match job.pids().first() {
+            Some(pid) if with_pids => format!("{pid} "),
+            _ => String::new(),
+        };
+        format!(
+            "[{id}]{marker}  {pids}{:<24}{}{background}",
+            state.to_string(),
+            job.command
+        )
+    }
+
+    pub fn is_snapshot(&self) -> bool {
+        self.snapshot
+    }
+
+    /// Catches up on what's happened to every job without blocking
+    pub fn poll(&mut self) {
+        if !self.snapshot {
+            self.jobs.iter_mut().for_each(Job::poll);
+        }
+    }
+
+    /// Checks on every job without blocking, giving back descriptions of the ones that finished
+    /// or stopped since the user last heard about them. Finished jobs are then forgotten.
+    pub fn report_changes(&mut self) -> Vec<String> {
+        let mut reports = vec![];
+        self.poll();
+        for id in self.ids() {
+            let Some(job) = self.get_mut(id) else {
+                continue;
+            };
+            let state = job.state();
+            if state == job.reported || state == State::Running {
+                job.reported = state;
+                continue;
+            }
+            job.reported = state;
+            reports.push(self.describe(id, false));
+            if let State::Done(_) = state {
+                self.remove(id);
+            }
+        }
+        reports
+    }
+}
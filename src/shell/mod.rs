@@ -0,0 +1,4 @@
+pub mod jobs;
+pub mod options;
+pub mod state;
+pub mod variables;
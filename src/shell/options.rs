@@ -0,0 +1,61 @@
+/// Options that change how the shell behaves, toggled with `shopt` or `set -o`
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    pub dotglob: bool,
+    pub failglob: bool,
+    pub globstar: bool,
+    pub nullglob: bool,
+    /// Whether aliases are expanded, which is only the default for interactive shells
+    pub expand_aliases: bool,
+    /// Keeps Ctrl-D on an empty line from leaving the shell
+    pub ignoreeof: bool,
+    /// Which keys the line editor uses, where only one of them can be on at a time
+    pub emacs: bool,
+    pub vi: bool,
+}
+
+impl Options {
+    /// The options `shopt` toggles
+    pub fn names() -> [&'static str; 5] {
+        [
+            "dotglob",
+            "expand_aliases",
+            "failglob",
+            "globstar",
+            "nullglob",
+        ]
+    }
+
+    /// The options `set -o` toggles
+    pub fn set_names() -> [&'static str; 3] {
+        ["emacs", "ignoreeof", "vi"]
+    }
+
+    pub fn get(&self, name: &str) -> Option<bool> {
+        match name {
+            "dotglob" => Some(self.dotglob),
+            "expand_aliases" => Some(self.expand_aliases),
+            "failglob" => Some(self.failglob),
+            "globstar" => Some(self.globstar),
+            "nullglob" => Some(self.nullglob),
+            "ignoreeof" => Some(self.ignoreeof),
+            "emacs" => Some(self.emacs),
+            "vi" => Some(self.vi),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "dotglob" => Some(&mut self.dotglob),
+            "expand_aliases" => Some(&mut self.expand_aliases),
+            "failglob" => Some(&mut self.failglob),
+            "globstar" => Some(&mut self.globstar),
+            "nullglob" => Some(&mut self.nullglob),
+            "ignoreeof" => Some(&mut self.ignoreeof),
+            "emacs" => Some(&mut self.emacs),
+            "vi" => Some(&mut self.vi),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::os::fd::RawFd;
+use std::process;
+
+use crate::builtins::history::History;
+use crate::parser::ast::{Command, List};
+use crate::parser::utils::{ParseError, parse_input};
+use crate::shell::jobs::Jobs;
+use crate::shell::options::Options;
+use crate::shell::variables::Variables;
+
+/// Everything the shell needs to remember between commands
+#[derive(Clone)]
+pub struct Shell {
+    pub history: History,
+    pub variables: Variables,
+    pub options: Options,
+    /// The bodies of the functions that have been defined
+    pub functions: HashMap<String, Command>,
+    pub aliases: BTreeMap<String, String>,
+    /// `$0`, the name of the script being run or of the shell itself
+    pub script_name: String,
+    /// `$1`, `$2` and so on
+    pub positional: Vec<String>,
+    pub last_status: i32,
+    /// The status of the last command substitution in the command being expanded
+    pub substitution_status: Option<i32>,
+    pub jobs: Jobs,
+    /// The terminal jobs are handed to, which is only set when job control is on
+    pub terminal: Option<RawFd>,
+    /// The last process started in the background, for `$!`
+    pub background_pid: Option<libc::pid_t>,
+    /// The script being run and the line it's up to, which error messages start with
+    pub script_line: Option<(String, usize)>,
+    /// How many loops the command being run is inside of, which `break` and `continue` can leave
+    pub loop_depth: usize,
+    /// How many functions and sourced files are running, which `return` can leave
+    pub call_depth: usize,
+    /// Set when Ctrl-C killed the foreground job, so the rest of the command line is dropped
+    pub interrupted: bool,
+    /// The pid of the shell as it was started, for `$$`, which stays the same in subshells and
+    /// command substitutions even though they're forked
+    pub pid: u32,
+    /// Whether commands are being read from the terminal, rather than a script or `-c`. A
+    /// subshell never is.
+    pub interactive: bool,
+}
+
+impl Shell {
+    pub fn new(history: History) -> Shell {
+        Shell {
+            history,
+            variables: Variables::from_env(),
+            options: Options::default(),
+            functions: HashMap::new(),
+            aliases: BTreeMap::new(),
+            script_name: env::args().next().unwrap_or_default(),
+            positional: vec![],
+            last_status: 0,
+            substitution_status: None,
+            jobs: Jobs::default(),
+            terminal: None,
+            background_pid: None,
+            script_line: None,
+            loop_depth: 0,
+            call_depth: 0,
+            interrupted: false,
+            interactive: false,
+            pid: process::id(),
+        }
+    }
+
+    /// Parses commands, expanding aliases in them if that's turned on
+    pub fn parse(&self, input: &str) -> Result<List, ParseError> {
+        match self.options.expand_aliases {
+            true => parse_input(input, &self.aliases),
+            false => parse_input(input, &BTreeMap::new()),
+        }
+    }
+
+    /// What error messages start with, like `script.sh: line 3: ` while a script is running
+    pub fn error_prefix(&self) -> String {
+        match &self.script_line {
+            Some((name, line)) => format!("{name}: line {line}: "),
+            None => String::new(),
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+use std::env;
+
+/// A variable's value is `None` when it's been exported before it was ever given a value
+#[derive(Clone, Debug)]
+pub struct Variable {
+    pub value: Option<String>,
+    pub exported: bool,
+    /// Every element when the variable is an array, where the first one is also its `value`
+    pub elements: Vec<String>,
+}
+
+/// The shell's variables, starting out with everything in the environment we were launched with
+#[derive(Clone, Debug)]
+pub struct Variables {
+    map: HashMap<String, Variable>,
+    /// One frame for each function that's running, holding what its local variables hid so
+    /// they can be put back when it returns
+    frames: Vec<Vec<(String, Option<Variable>)>>,
+}
+
+impl Variables {
+    pub fn from_env() -> Variables {
+        let map = env::vars()
+            .map(|(name, value)| {
+                let variable = Variable {
+                    value: Some(value),
+                    exported: true,
+                    elements: vec![],
+                };
+                (name, variable)
+            })
+            .collect();
+        Variables {
+            map,
+            frames: vec![],
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.map.get(name)?.value.as_deref()
+    }
+
+    /// Sets a variable's value, keeping it exported if it already was. For an array this sets its
+    /// first element.
+    pub fn set(&mut self, name: &str, value: String) {
+        let variable = self.entry(name);
+        if let Some(first) = variable.elements.first_mut() {
+            *first = value.clone();
+        }
+        variable.value = Some(value);
+    }
+
+    /// Makes a variable an array of `elements`
+    pub fn set_array(&mut self, name: &str, elements: Vec<String>) {
+        let variable = self.entry(name);
+        variable.value = elements.first().cloned();
+        variable.elements = elements;
+    }
+
+    /// Gives every element of an array, where a variable that isn't one counts as an array of
+    /// just its value
+    pub fn elements(&self, name: &str) -> Vec<&str> {
+        let Some(variable) = self.map.get(name) else {
+            return vec![];
+        };
+        match variable.elements.is_empty() {
+            true => variable.value.as_deref().into_iter().collect(),
+            false => variable.elements.iter().map(String::as_str).collect(),
+        }
+    }
+
+    pub fn export(&mut self, name: &str, value: Option<String>) {
+        let variable = self.entry(name);
+        variable.exported = true;
+        if value.is_some() {
+            variable.value = value;
+        }
+    }
+
+    pub fn unexport(&mut self, name: &str) {
+        if let Some(variable) = self.map.get_mut(name) {
+            variable.exported = false;
+        }
+    }
+
+    pub fn unset(&mut self, name: &str) {
+        self.map.remove(name);
+    }
+
+    /// The variables that get passed on to child processes, sorted by name
+    pub fn exported(&self) -> Vec<(String, String)> {
+        let mut exported: Vec<(String, String)> = self
+            .map
+            .iter()
+            .filter(|(_, variable)| variable.exported)
+            .filter_map(|(name, variable)| Some((name.clone(), variable.value.clone()?)))
+            .collect();
+        exported.sort();
+        exported
+    }
+
+    pub fn push_frame(&mut self) {
+        self.frames.push(vec![]);
+    }
+
+    /// Puts back every variable the innermost function made local
+    pub fn pop_frame(&mut self) {
+        let Some(frame) = self.frames.pop() else {
+            return;
+        };
+        for (name, hidden) in frame.into_iter().rev() {
+            match hidden {
+                Some(variable) => self.map.insert(name, variable),
+                None => self.map.remove(&name),
+            };
+        }
+    }
+
+    /// Makes a variable local to the innermost function, starting out without a value. Functions
+    /// it calls see the local one too. Fails outside of a function.
+    pub fn make_local(&mut self, name: &str) -> bool {
+        let Some(frame) = self.frames.last_mut() else {
+            return false;
+        };
+        if frame.iter().any(|(local, _)| local == name) {
+            return true;
+        }
+        frame.push((name.to_string(), self.map.remove(name)));
+        true
+    }
+
+    fn entry(&mut self, name: &str) -> &mut Variable {
+        self.map.entry(name.to_string()).or_insert(Variable {
+            value: None,
+            exported: false,
+            elements: vec![],
+        })
+    }
+}
@@ -0,0 +1,962 @@
+use std::env;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::mem;
+use std::os::unix::process::ExitStatusExt;
+use std::process::{self, ExitStatus};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use anyhow::{Result, anyhow};
+
+use crate::builtins::BUILTINS;
+use crate::builtins::alias::alias_fn;
+use crate::builtins::bg::bg_fn;
+use crate::builtins::cd::cd_fn;
+use crate::builtins::disown::disown_fn;
+use crate::builtins::export::export_fn;
+use crate::builtins::fg::fg_fn;
+use crate::builtins::history::history_fn;
+use crate::builtins::jobs::jobs_fn;
+use crate::builtins::let_fn::let_fn;
+use crate::builtins::local::local_fn;
+use crate::builtins::pwd::pwd_fn;
+use crate::builtins::set::set_fn;
+use crate::builtins::shift::shift_fn;
+use crate::builtins::shopt::shopt_fn;
+use crate::builtins::source::source_fn;
+use crate::builtins::test::{conditional_fn, test_fn};
+use crate::builtins::type_fn::type_fn;
+use crate::builtins::unalias::unalias_fn;
+use crate::builtins::unset::unset_fn;
+use crate::builtins::wait::wait_fn;
+use crate::expansion::arithmetic::evaluate;
+use crate::expansion::parameter::UnsetParameterError;
+use crate::expansion::pattern::pattern_matches;
+use crate::expansion::utils::{expand_pattern, expand_word, expand_word_to_string};
+use crate::parser::ast::{
+    AndOr, CaseItem, Command, CompoundCommand, Connector, List, Pipeline, RedirectKind,
+    Redirection, SimpleCommand, Word,
+};
+use crate::shell::jobs::{Job, Member, State};
+use crate::shell::state::Shell;
+use crate::subprocesses::fds::{Fd, Fds};
+use crate::subprocesses::job_control::{ProcessGroup, take_sigint, wait_for_foreground};
+use crate::subprocesses::utils::{Spawned, exit_code, spawn_program};
+
+/// How running part of the AST finished
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Outcome {
+    Status(i32),
+    Exit(i32),
+    /// `break` or `continue`, with the number of enclosing loops it still has to get through
+    Break(usize),
+    Continue(usize),
+    /// `return` from a function or sourced file
+    Return(i32),
+}
+
+impl Outcome {
+    pub fn status(&self) -> i32 {
+        match self {
+            Outcome::Status(status) | Outcome::Exit(status) | Outcome::Return(status) => *status,
+            Outcome::Break(_) | Outcome::Continue(_) => 0,
+        }
+    }
+}
+
+/// A command that's been started, which might still be running
+enum Stage {
+    Started(Member),
+    Done(Outcome),
+}
+
+impl Stage {
+    fn into_member(self) -> Member {
+        match self {
+            Stage::Started(member) => member,
+            Stage::Done(outcome) => Member::Done(outcome.status()),
+        }
+    }
+}
+
+pub fn execute_list(list: &List, shell: &mut Shell, fds: &Fds) -> Result<Outcome> {
+    shell.interrupted = false;
+    take_sigint();
+    run_list(list, shell, fds)
+}
+
+/// Runs a list in a subshell and collects everything it writes to stdout, for `$(...)`
+pub fn capture_output(list: &List, shell: &mut Shell) -> Result<(Vec<u8>, i32)> {
+    let (mut reader, writer) = io::pipe()?;
+    let mut fds = Fds::standard();
+    fds.set(1, Fd::new(writer));
+    let pid = fork_shell(shell, &fds, None, |shell, fds| run_list(list, shell, fds))?;
+    // Our copy of the write end has to be closed too, or the output would never end
+    drop(fds);
+
+    let mut output = vec![];
+    reader.read_to_end(&mut output)?;
+    Ok((output, wait_pid(pid)))
+}
+
+fn run_list(list: &List, shell: &mut Shell, fds: &Fds) -> Result<Outcome> {
+    let mut outcome = Outcome::Status(0);
+    for and_or in &list.items {
+        outcome = match and_or.background {
+            true => start_background(and_or, shell, fds)?,
+            false => run_and_or(and_or, shell, fds)?,
+        };
+        if matches!(outcome, Outcome::Status(_)) && interrupted_by_sigint(shell) {
+            outcome = Outcome::Status(130);
+        }
+        if !matches!(outcome, Outcome::Status(_)) || shell.interrupted {
+            break;
+        }
+    }
+    Ok(outcome)
+}
+
+/// Notices Ctrl-C being pressed while the shell was running builtins itself, which stops
+/// whatever list or loop is running like an interrupted command would
+fn interrupted_by_sigint(shell: &mut Shell) -> bool {
+    if !take_sigint() {
+        return false;
+    }
+    println!();
+    shell.interrupted = true;
+    shell.last_status = 130;
+    true
+}
+
+fn run_and_or(and_or: &AndOr, shell: &mut Shell, fds: &Fds) -> Result<Outcome> {
+    let mut outcome = run_pipeline(&and_or.first, shell, fds)?;
+    for (connector, pipeline) in &and_or.rest {
+        if shell.interrupted {
+            break;
+        }
+        let should_run = match (connector, outcome) {
+            (Connector::And, Outcome::Status(status)) => status == 0,
+            (Connector::Or, Outcome::Status(status)) => status != 0,
+            _ => break,
+        };
+        if should_run {
+            outcome = run_pipeline(pipeline, shell, fds)?;
+        }
+    }
+    Ok(outcome)
+}
+
+/// Starts an and-or list as a job without waiting for it, like `cmd &`
+fn start_background(and_or: &AndOr, shell: &mut Shell, fds: &Fds) -> Result<Outcome> {
+    let mut fds = fds.clone();
+    if shell.terminal.is_none() {
+        // Without job control the job can't be handed the terminal, so it gets nothing to read
+        fds.set(0, Fd::new(File::open("/dev/null")?));
+    }
+
+    let mut group = shell
+        .terminal
+        .map(|terminal| ProcessGroup::new(terminal, false));
+    let members = if and_or.rest.is_empty() {
+        start_pipeline(&and_or.first, shell, &fds, group.as_mut())?
+    } else {
+        // Whether each part runs depends on the one before, so a subshell works through them
+        let pid = fork_shell(shell, &fds, group.as_mut(), |shell, fds| {
+            run_and_or(and_or, shell, fds)
+        })?;
+        vec![Member::Process {
+            pid,
+            state: State::Running,
+        }]
+    };
+
+    let mut job = Job::new(
+        group.map_or(0, |group| group.pgid),
+        and_or.to_string(),
+        members,
+    );
+    // With `&&` or `||` the subshell running them has already turned the status round
+    job.negated = and_or.rest.is_empty() && and_or.first.negated;
+    if let Some(pid) = job.pids().last() {
+        shell.background_pid = Some(*pid);
+    }
+    let id = shell.jobs.insert(job);
+    if shell.terminal.is_some() {
+        let pid = shell.background_pid.unwrap_or_default();
+        eprintln!("[{id}] {pid}");
+    }
+
+    shell.last_status = 0;
+    Ok(Outcome::Status(0))
+}
+
+fn run_pipeline(pipeline: &Pipeline, shell: &mut Shell, fds: &Fds) -> Result<Outcome> {
+    let outcome = match run_pipeline_commands(pipeline, shell, fds)? {
+        Outcome::Status(status) if pipeline.negated => Outcome::Status((status == 0) as i32),
+        outcome => outcome,
+    };
+    shell.last_status = outcome.status();
+    Ok(outcome)
+}
+
+fn run_pipeline_commands(pipeline: &Pipeline, shell: &mut Shell, fds: &Fds) -> Result<Outcome> {
+    let mut group = shell
+        .terminal
+        .map(|terminal| ProcessGroup::new(terminal, true));
+    let members = match pipeline.commands.as_slice() {
+        [command] => match start_command(command, shell, fds, false, group.as_mut())? {
+            Stage::Done(outcome) => {
+                shell.last_status = outcome.status();
+                return Ok(outcome);
+            }
+            stage => vec![stage.into_member()],
+        },
+        _ => start_pipeline(pipeline, shell, fds, group.as_mut())?,
+    };
+
+    let job = Job::new(
+        group.map_or(0, |group| group.pgid),
+        pipeline.to_string(),
+        members,
+    );
+    // `exit` in a pipeline only leaves its own part of it
+    let outcome = Outcome::Status(wait_for_foreground(shell, job, false));
+    shell.last_status = outcome.status();
+    Ok(outcome)
+}
+
+/// Starts every command in a pipeline at once, connected by pipes
+fn start_pipeline(
+    pipeline: &Pipeline,
+    shell: &mut Shell,
+    fds: &Fds,
+    mut group: Option<&mut ProcessGroup>,
+) -> Result<Vec<Member>> {
+    let last = pipeline.commands.len() - 1;
+    let mut members = vec![];
+    let mut input = None;
+    for (i, command) in pipeline.commands.iter().enumerate() {
+        let mut stage_fds = fds.clone();
+        if let Some(input) = input.take() {
+            stage_fds.set(0, input);
+        }
+        if i != last {
+            let (reader, writer) = io::pipe()?;
+            stage_fds.set(1, Fd::new(writer));
+            input = Some(Fd::new(reader));
+        }
+        let stage = start_command(command, shell, &stage_fds, true, group.as_deref_mut())?;
+        members.push(stage.into_member());
+    }
+    Ok(members)
+}
+
+/// Starts a command without waiting for it. When it's `concurrent` it has to run alongside other
+/// commands, so anything the shell runs itself goes on its own thread or in a subshell.
+fn start_command(
+    command: &Command,
+    shell: &mut Shell,
+    fds: &Fds,
+    concurrent: bool,
+    group: Option<&mut ProcessGroup>,
+) -> Result<Stage> {
+    let redirections = match command {
+        Command::Simple(simple_command) => &simple_command.redirections,
+        Command::Compound(_, redirections) => redirections,
+        // The body's redirections are applied each time it's called instead
+        Command::Function { .. } => &vec![],
+    };
+    let fds = match apply_redirections(redirections, shell, fds) {
+        Ok(fds) => fds,
+        Err(e) => return Ok(Stage::Done(expansion_failed(shell, fds, e))),
+    };
+
+    match command {
+        Command::Simple(simple_command) => {
+            start_simple_command(simple_command, shell, &fds, concurrent, group)
+        }
+        Command::Compound(compound, _)
+            if concurrent || matches!(compound, CompoundCommand::Subshell(_)) =>
+        {
+            let pid = fork_shell(shell, &fds, group, |shell, fds| {
+                run_compound(compound, shell, fds)
+            })?;
+            Ok(Stage::Started(Member::Process {
+                pid,
+                state: State::Running,
+            }))
+        }
+        Command::Compound(compound, _) => Ok(Stage::Done(run_compound(compound, shell, &fds)?)),
+        Command::Function { name, body } => {
+            // A definition in a pipeline is only made in that part of it, which then goes away
+            if !concurrent {
+                shell.functions.insert(name.clone(), (**body).clone());
+            }
+            Ok(Stage::Done(Outcome::Status(0)))
+        }
+    }
+}
+
+/// Runs a function's body in the shell with `args` as its positional parameters
+fn start_function(
+    body: &Command,
+    args: Vec<String>,
+    shell: &mut Shell,
+    fds: &Fds,
+    group: Option<&mut ProcessGroup>,
+) -> Result<Stage> {
+    let caller_positional = mem::replace(&mut shell.positional, args);
+    // Loops the function was called from can't be left from inside it
+    let caller_loop_depth = mem::take(&mut shell.loop_depth);
+    shell.variables.push_frame();
+    shell.call_depth += 1;
+
+    let stage = start_command(body, shell, fds, false, group);
+
+    shell.call_depth -= 1;
+    shell.variables.pop_frame();
+    shell.loop_depth = caller_loop_depth;
+    shell.positional = caller_positional;
+    match stage? {
+        Stage::Done(Outcome::Return(status)) => Ok(Stage::Done(Outcome::Status(status))),
+        stage => Ok(stage),
+    }
+}
+
+/// Runs a compound command in the shell. A subshell's list runs here too, once it's been forked.
+fn run_compound(compound: &CompoundCommand, shell: &mut Shell, fds: &Fds) -> Result<Outcome> {
+    match compound {
+        CompoundCommand::BraceGroup(list) | CompoundCommand::Subshell(list) => {
+            run_list(list, shell, fds)
+        }
+        CompoundCommand::If {
+            branches,
+            otherwise,
+        } => {
+            for (condition, body) in branches {
+                let tested = run_list(condition, shell, fds)?;
+                if !matches!(tested, Outcome::Status(_)) || shell.interrupted {
+                    return Ok(tested);
+                }
+                if tested.status() == 0 {
+                    return run_list(body, shell, fds);
+                }
+            }
+            match otherwise {
+                Some(otherwise) => run_list(otherwise, shell, fds),
+                None => Ok(Outcome::Status(0)),
+            }
+        }
+        CompoundCommand::While {
+            until,
+            condition,
+            body,
+        } => {
+            shell.loop_depth += 1;
+            let outcome = run_while(*until, condition, body, shell, fds);
+            shell.loop_depth -= 1;
+            outcome
+        }
+        CompoundCommand::For { name, words, body } => {
+            let values = match words {
+                Some(words) => match expand_words(words, shell) {
+                    Ok(values) => values,
+                    Err(e) => return Ok(expansion_failed(shell, fds, e)),
+                },
+                None => shell.positional.clone(),
+            };
+            shell.loop_depth += 1;
+            let outcome = run_for(name, values, body, shell, fds);
+            shell.loop_depth -= 1;
+            outcome
+        }
+        CompoundCommand::Case { word, items } => match run_case(word, items, shell, fds) {
+            Ok(outcome) => Ok(outcome),
+            Err(e) => Ok(expansion_failed(shell, fds, e)),
+        },
+        CompoundCommand::Arithmetic(expression) => {
+            let value = expand_word_to_string(shell, expression)
+                .and_then(|expression| evaluate(shell, &expression));
+            match value {
+                Ok(value) => Ok(Outcome::Status(if value != 0 { 0 } else { 1 })),
+                Err(e) => Ok(expansion_failed(shell, fds, e)),
+            }
+        }
+        CompoundCommand::Conditional(expression) => {
+            match conditional_fn(shell, expression, &mut fds.writer(2)) {
+                Ok(status) => Ok(Outcome::Status(status)),
+                Err(e) => Ok(expansion_failed(shell, fds, e)),
+            }
+        }
+    }
+}
+
+fn expand_words(words: &[Word], shell: &mut Shell) -> Result<Vec<String>> {
+    let mut values = vec![];
+    for word in words {
+        values.extend(expand_word(shell, word)?);
+    }
+    Ok(values)
+}
+
+/// Works out whether a loop carries on after part of it finished with `outcome`, giving back
+/// what the whole loop finishes with if it doesn't
+fn leave_loop(outcome: Outcome, shell: &Shell) -> Option<Outcome> {
+    match outcome {
+        Outcome::Break(1) => Some(Outcome::Status(0)),
+        Outcome::Break(levels) => Some(Outcome::Break(levels - 1)),
+        Outcome::Continue(1) => None,
+        Outcome::Continue(levels) => Some(Outcome::Continue(levels - 1)),
+        Outcome::Exit(_) | Outcome::Return(_) => Some(outcome),
+        Outcome::Status(_) if shell.interrupted => Some(outcome),
+        Outcome::Status(_) => None,
+    }
+}
+
+fn run_while(
+    until: bool,
+    condition: &List,
+    body: &List,
+    shell: &mut Shell,
+    fds: &Fds,
+) -> Result<Outcome> {
+    let mut status = 0;
+    loop {
+        if interrupted_by_sigint(shell) {
+            return Ok(Outcome::Status(130));
+        }
+        let tested = run_list(condition, shell, fds)?;
+        if !matches!(tested, Outcome::Status(_)) || shell.interrupted {
+            match leave_loop(tested, shell) {
+                Some(outcome) => return Ok(outcome),
+                None => continue,
+            }
+        }
+        if (tested.status() == 0) == until {
+            return Ok(Outcome::Status(status));
+        }
+
+        let outcome = run_list(body, shell, fds)?;
+        status = outcome.status();
+        if let Some(outcome) = leave_loop(outcome, shell) {
+            return Ok(outcome);
+        }
+    }
+}
+
+fn run_for(
+    name: &str,
+    values: Vec<String>,
+    body: &List,
+    shell: &mut Shell,
+    fds: &Fds,
+) -> Result<Outcome> {
+    let mut status = 0;
+    for value in values {
+        if interrupted_by_sigint(shell) {
+            return Ok(Outcome::Status(130));
+        }
+        shell.variables.set(name, value);
+        let outcome = run_list(body, shell, fds)?;
+        status = outcome.status();
+        if let Some(outcome) = leave_loop(outcome, shell) {
+            return Ok(outcome);
+        }
+    }
+    Ok(Outcome::Status(status))
+}
+
+/// Runs the commands for the first pattern that matches the word, if there is one
+fn run_case(word: &Word, items: &[CaseItem], shell: &mut Shell, fds: &Fds) -> Result<Outcome> {
+    let value = expand_word_to_string(shell, word)?;
+    for item in items {
+        for pattern in &item.patterns {
+            if pattern_matches(&expand_pattern(shell, pattern)?, &value) {
+                return run_list(&item.body, shell, fds);
+            }
+        }
+    }
+    Ok(Outcome::Status(0))
+}
+
+/// Runs a builtin that's part of a pipeline on its own thread, with a copy of the shell like a
+/// subshell would have
+fn start_builtin_thread(command: String, args: Vec<String>, shell: &Shell, fds: &Fds) -> Stage {
+    let mut shell = shell.clone();
+    shell.terminal = None;
+    // The thread owns its copy of the descriptors, so its end of any pipe is closed once it's done
+    let fds = fds.clone();
+    Stage::Started(Member::Thread(thread::spawn(move || {
+        run_builtin(&command, args, &mut shell, &fds, true).status()
+    })))
+}
+
+/// Works out the file descriptors a command runs with by applying its redirections in order on
+/// top of the ones it inherits
+fn apply_redirections(redirections: &[Redirection], shell: &mut Shell, fds: &Fds) -> Result<Fds> {
+    let mut fds = fds.clone();
+    for redirection in redirections {
+        let fd = redirection.fd;
+        let target = expand_word_to_string(shell, &redirection.target)?;
+        let kind = match &redirection.kind {
+            // `>&file` is an older way of writing `&>file`
+            RedirectKind::DupOutput
+                if fd == 1 && target != "-" && target.parse::<i32>().is_err() =>
+            {
+                RedirectKind::OutputAll
+            }
+            kind => kind.clone(),
+        };
+
+        let mut options = OpenOptions::new();
+        match kind {
+            RedirectKind::Output | RedirectKind::OutputAll => {
+                options.write(true).create(true).truncate(true);
+            }
+            RedirectKind::Append | RedirectKind::AppendAll => {
+                options.create(true).append(true);
+            }
+            RedirectKind::Input => {
+                options.read(true);
+            }
+            RedirectKind::ReadWrite => {
+                options.read(true).write(true).create(true);
+            }
+            RedirectKind::HereDoc => {
+                fds.set(fd, Fd::new(here_document(&target)?));
+                continue;
+            }
+            RedirectKind::HereString => {
+                fds.set(fd, Fd::new(here_document(&format!("{target}\n"))?));
+                continue;
+            }
+            RedirectKind::DupOutput | RedirectKind::DupInput => {
+                if target == "-" {
+                    fds.close(fd);
+                } else if let Ok(source) = target.parse::<i32>() {
+                    fds.duplicate(fd, source)
+                        .map_err(|e| anyhow!("{target}: {e}"))?;
+                } else {
+                    return Err(anyhow!("{target}: ambiguous redirect"));
+                }
+                continue;
+            }
+        }
+
+        let file = options
+            .open(&target)
+            .map_err(|e| anyhow!("{target}: {e}"))?;
+        let file = Fd::new(file);
+        if matches!(kind, RedirectKind::OutputAll | RedirectKind::AppendAll) {
+            fds.set(1, file.clone());
+            fds.set(2, file);
+        } else {
+            fds.set(fd, file);
+        }
+    }
+    Ok(fds)
+}
+
+/// Puts the body of a here-document in a file for a command to read. The file is deleted
+/// straight away so it can't be left behind, and it'll go once nothing has it open.
+fn here_document(body: &str) -> Result<File> {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let name = format!(
+        "rustsh-heredoc-{}-{}",
+        process::id(),
+        COUNT.fetch_add(1, Ordering::Relaxed)
+    );
+    let path = env::temp_dir().join(name);
+
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    fs::remove_file(&path)?;
+    file.write_all(body.as_bytes())?;
+    file.seek(SeekFrom::Start(0))?;
+    Ok(file)
+}
+
+fn start_simple_command(
+    simple_command: &SimpleCommand,
+    shell: &mut Shell,
+    fds: &Fds,
+    concurrent: bool,
+    group: Option<&mut ProcessGroup>,
+) -> Result<Stage> {
+    shell.substitution_status = None;
+    let mut words = vec![];
+    for word in &simple_command.words {
+        match expand_word(shell, word) {
+            Ok(fields) => words.extend(fields),
+            Err(e) => return Ok(Stage::Done(expansion_failed(shell, fds, e))),
+        }
+    }
+    let mut assignments = vec![];
+    for assignment in &simple_command.assignments {
+        match expand_word_to_string(shell, &assignment.value) {
+            Ok(value) => assignments.push((assignment.name.clone(), value)),
+            Err(e) => return Ok(Stage::Done(expansion_failed(shell, fds, e))),
+        }
+    }
+
+    let mut words = words.into_iter();
+    let Some(command) = words.next() else {
+        // Without a command, assignments stay in the shell, unless it's only a pipeline's copy
+        if !concurrent {
+            for (name, value) in assignments {
+                shell.variables.set(&name, value);
+            }
+        }
+        // `x=$(cmd)` takes on the status of `cmd`
+        let status = shell.substitution_status.unwrap_or(0);
+        return Ok(Stage::Done(Outcome::Status(status)));
+    };
+    let args: Vec<String> = words.collect();
+
+    if let Some(body) = shell.functions.get(&command).cloned() {
+        if concurrent {
+            let pid = fork_shell(shell, fds, group, |shell, fds| {
+                match start_function(&body, args, shell, fds, None)? {
+                    Stage::Done(outcome) => Ok(outcome),
+                    Stage::Started(member) => {
+                        let mut job = Job::new(0, String::new(), vec![member]);
+                        job.wait();
+                        Ok(Outcome::Status(job.status()))
+                    }
+                }
+            })?;
+            return Ok(Stage::Started(Member::Process {
+                pid,
+                state: State::Running,
+            }));
+        }
+        return start_function(&body, args, shell, fds, group);
+    }
+
+    if !BUILTINS.contains(&command.as_str()) {
+        // Assignments before an external command only go to that command's environment
+        let mut env = shell.variables.exported();
+        env.retain(|(name, _)| !assignments.iter().any(|(assigned, _)| assigned == name));
+        env.extend(assignments);
+        return match spawn_program(&shell.error_prefix(), &command, args, &env, fds, group)? {
+            Spawned::Child(pid) => Ok(Stage::Started(Member::Process {
+                pid,
+                state: State::Running,
+            })),
+            Spawned::Failed(status) => Ok(Stage::Done(Outcome::Status(status))),
+        };
+    }
+
+    if concurrent {
+        return Ok(start_builtin_thread(command, args, shell, fds));
+    }
+    Ok(Stage::Done(run_builtin(&command, args, shell, fds, false)))
+}
+
+/// Runs a builtin, where `threaded` means it's running in a copy of the shell on another thread
+/// and mustn't change anything the whole process shares
+fn run_builtin(
+    command: &str,
+    args: Vec<String>,
+    shell: &mut Shell,
+    fds: &Fds,
+    threaded: bool,
+) -> Outcome {
+    let mut stdout = fds.writer(1);
+    let mut stderr = fds.writer(2);
+    let result = match command {
+        "echo" => writeln!(stdout, "{}", args.join(" "))
+            .map(|_| 0)
+            .map_err(Into::into),
+        "history" => history_fn(&mut shell.history, args, &mut stdout, &mut stderr),
+        "exit" => return exit_fn(shell, &args, &mut stderr),
+        "break" | "continue" => return loop_control_fn(command, shell, &args, &mut stderr),
+        "return" => return return_fn(shell, &args, &mut stderr),
+        "local" => local_fn(&mut shell.variables, args, &mut stderr),
+        "alias" => alias_fn(&mut shell.aliases, args, &mut stdout, &mut stderr),
+        "unalias" => unalias_fn(&mut shell.aliases, args, &mut stderr),
+        "source" | "." => match source_fn(shell, args, fds) {
+            Ok(outcome) => return outcome,
+            Err(e) => Err(e),
+        },
+        "pwd" => pwd_fn(&mut stdout),
+        "test" | "[" => test_fn(command, &shell.variables, args, &mut stderr),
+        "let" => let_fn(shell, args, &mut stderr),
+        "type" => type_fn(shell, &args.join(" "), &mut stdout, &mut stderr),
+        "cd" => cd_fn(args, threaded, shell.variables.get("HOME"), &mut stderr),
+        "export" => export_fn(&mut shell.variables, args, &mut stdout, &mut stderr),
+        "unset" => unset_fn(shell, args, &mut stderr),
+        "shopt" => shopt_fn(&mut shell.options, args, &mut stdout, &mut stderr),
+        "set" => set_fn(shell, args, &mut stdout, &mut stderr),
+        "shift" => shift_fn(&mut shell.positional, args, &mut stderr),
+        "jobs" => jobs_fn(&mut shell.jobs, args, &mut stdout, &mut stderr),
+        "fg" => fg_fn(shell, args, &mut stdout, &mut stderr),
+        "bg" => bg_fn(shell, args, &mut stdout, &mut stderr),
+        "wait" => wait_fn(&mut shell.jobs, args, &mut stderr),
+        "disown" => disown_fn(&mut shell.jobs, args, &mut stderr),
+        _ => unreachable!("{command} is in BUILTINS"),
+    };
+
+    // A failing builtin shouldn't take the whole shell down with it
+    let status = result.unwrap_or_else(|e| {
+        let broken_pipe = e
+            .downcast_ref::<io::Error>()
+            .is_some_and(|e| e.kind() == io::ErrorKind::BrokenPipe);
+        // Like a program killed by SIGPIPE, quietly stop once nothing is reading
+        if broken_pipe {
+            return 128 + libc::SIGPIPE;
+        }
+        let _ = writeln!(stderr, "{}{command}: {e}", shell.error_prefix());
+        1
+    });
+    Outcome::Status(status)
+}
+
+/// Reports an error on the command's stderr, after the script's name and line if there is one
+fn report_error(shell: &Shell, fds: &Fds, e: impl fmt::Display) {
+    let _ = writeln!(fds.writer(2), "{}{e}", shell.error_prefix());
+}
+
+/// Reports an error expanding a command's words, giving what the command comes to. `${name:?}`
+/// failing makes a shell that isn't interactive exit.
+fn expansion_failed(shell: &Shell, fds: &Fds, e: anyhow::Error) -> Outcome {
+    report_error(shell, fds, &e);
+    match !shell.interactive && e.is::<UnsetParameterError>() {
+        true => Outcome::Exit(1),
+        false => Outcome::Status(1),
+    }
+}
+
+/// `exit` leaves with the given status, or the status of the last command if there isn't one
+fn exit_fn(shell: &Shell, args: &[String], stderr: &mut dyn Write) -> Outcome {
+    match args.first() {
+        None => Outcome::Exit(shell.last_status),
+        Some(arg) => match arg.parse::<i32>() {
+            Ok(status) => Outcome::Exit(status & 0xff),
+            Err(_) => {
+                let prefix = shell.error_prefix();
+                let _ = writeln!(stderr, "{prefix}exit: {arg}: numeric argument required");
+                Outcome::Exit(2)
+            }
+        },
+    }
+}
+
+/// `break` leaves the `n`th enclosing loop and `continue` starts its next round, where `n` is 1
+/// unless it's given
+fn loop_control_fn(
+    command: &str,
+    shell: &Shell,
+    args: &[String],
+    stderr: &mut dyn Write,
+) -> Outcome {
+    let levels = match args.first().map(|arg| (arg, arg.parse::<i64>())) {
+        None => 1,
+        Some((_, Ok(levels))) if levels >= 1 => levels as usize,
+        Some((arg, Ok(_))) => {
+            let _ = writeln!(stderr, "{command}: {arg}: loop count out of range");
+            return Outcome::Status(1);
+        }
+        Some((arg, Err(_))) => {
+            let _ = writeln!(stderr, "{command}: {arg}: numeric argument required");
+            return Outcome::Status(1);
+        }
+    };
+    if shell.loop_depth == 0 {
+        let _ = writeln!(
+            stderr,
+            "{command}: only meaningful in a `for', `while', or `until' loop"
+        );
+        return Outcome::Status(0);
+    }
+
+    // Asking to leave more loops than there are just leaves all of them
+    let levels = levels.min(shell.loop_depth);
+    match command {
+        "break" => Outcome::Break(levels),
+        _ => Outcome::Continue(levels),
+    }
+}
+
+/// `return` leaves the function or sourced file that's running with the given status, or the
+/// status of the last command if there isn't one
+fn return_fn(shell: &Shell, args: &[String], stderr: &mut dyn Write) -> Outcome {
+    if shell.call_depth == 0 {
+        let _ = writeln!(
+            stderr,
+            "return: can only `return' from a function or sourced script"
+        );
+        return Outcome::Status(1);
+    }
+    match args.first() {
+        None => Outcome::Return(shell.last_status),
+        Some(arg) => match arg.parse::<i32>() {
+            Ok(status) => Outcome::Return(status & 0xff),
+            Err(_) => {
+                let _ = writeln!(stderr, "return: {arg}: numeric argument required");
+                Outcome::Return(2)
+            }
+        },
+    }
+}
+
+/// Runs something in a forked copy of the shell so that things like `cd` don't leak out of it,
+/// giving back the child's pid. With job control the child goes in the process `group`.
+fn fork_shell(
+    shell: &mut Shell,
+    fds: &Fds,
+    group: Option<&mut ProcessGroup>,
+    run: impl FnOnce(&mut Shell, &Fds) -> Result<Outcome>,
+) -> Result<libc::pid_t> {
+    io::stdout().flush()?;
+
+    match unsafe { libc::fork() } {
+        -1 => Err(io::Error::last_os_error().into()),
+        0 => {
+            // Joining hands over the terminal, which is one of the descriptors about to be closed
+            if let Some(group) = group {
+                group.join();
+            }
+            fds.close_others();
+            // Job control is only for the top level shell
+            shell.terminal = None;
+            shell.interactive = false;
+            shell.jobs = shell.jobs.clone();
+
+            let status = match run(shell, fds) {
+                Ok(outcome) => outcome.status(),
+                Err(e) => {
+                    report_error(shell, fds, e);
+                    1
+                }
+            };
+            let _ = io::stdout().flush();
+            unsafe { libc::_exit(status) }
+        }
+        pid => {
+            if let Some(group) = group {
+                group.add(pid);
+            }
+            Ok(pid)
+        }
+    }
+}
+
+fn wait_pid(pid: libc::pid_t) -> i32 {
+    let mut status = 0;
+    unsafe { libc::waitpid(pid, &mut status, 0) };
+    exit_code(ExitStatus::from_raw(status))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::builtins::history::History;
+    use crate::subprocesses::job_control::catch_sigint;
+
+    /// Commands share the process's signal flag and working directory, so the tests run them one
+    /// at a time
+    static RUNNING: Mutex<()> = Mutex::new(());
+
+    fn run(shell: &mut Shell, input: &str) -> Outcome {
+        let _running = RUNNING.lock().unwrap_or_else(|e| e.into_inner());
+        let list = shell.parse(input).unwrap();
+        execute_list(&list, shell, &Fds::standard()).unwrap()
+    }
+
+    #[test]
+    fn unset_parameter_error_exits_when_not_interactive() {
+        let input = "{ x=${unset_in_test:?oops}; } 2>/dev/null; y=after";
+        let mut shell = Shell::new(History::new());
+        assert_eq!(run(&mut shell, input), Outcome::Exit(1));
+        assert_eq!(shell.variables.get("y"), None);
+
+        shell.interactive = true;
+        assert_eq!(run(&mut shell, input), Outcome::Status(0));
+        assert_eq!(shell.variables.get("y"), Some("after"));
+    }
+
+    #[test]
+    fn negated_pipeline() {
+        let mut shell = Shell::new(History::new());
+        assert_eq!(run(&mut shell, "! false"), Outcome::Status(0));
+        assert_eq!(run(&mut shell, "! true"), Outcome::Status(1));
+        run(&mut shell, "if ! false; then y=yes; fi");
+        assert_eq!(shell.variables.get("y"), Some("yes"));
+
+        let list = shell.parse("! true | false").unwrap();
+        assert_eq!(list.to_string(), "! true | false");
+    }
+
+    #[test]
+    fn sigint_stops_builtin_loops() {
+        catch_sigint();
+        let mut shell = Shell::new(History::new());
+        for input in [
+            "while [ 1 = 1 ]; do x=1; done",
+            "for i in 1 2 3; do until false; do x=2; done; done",
+        ] {
+            let interrupt = thread::spawn(|| {
+                thread::sleep(std::time::Duration::from_millis(200));
+                unsafe { libc::raise(libc::SIGINT) };
+            });
+            assert_eq!(run(&mut shell, input), Outcome::Status(130));
+            assert_eq!(shell.last_status, 130);
+            interrupt.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn errors_go_to_the_command_stderr() {
+        let log = env::temp_dir().join(format!("rustsh-test-{}", process::id()));
+        let mut shell = Shell::new(History::new());
+        shell.script_line = Some(("script.sh".to_string(), 3));
+        // So that `${name?}` doesn't end the test's commands early
+        shell.interactive = true;
+        let input = format!(
+            "{{ echo hi > /nonexistent/x; y=${{unset_in_test?oops}}; exit abc; }} 2>{}",
+            log.display()
+        );
+        run(&mut shell, &input);
+        let errors = fs::read_to_string(&log).unwrap();
+        fs::remove_file(&log).unwrap();
+        let lines: Vec<&str> = errors.lines().collect();
+        assert_eq!(lines.len(), 3, "{errors}");
+        assert!(lines[0].starts_with("script.sh: line 3: /nonexistent/x: "));
+        assert_eq!(lines[1], "script.sh: line 3: unset_in_test: oops");
+        assert_eq!(
+            lines[2],
+            "script.sh: line 3: exit: abc: numeric argument required"
+        );
+    }
+
+    #[test]
+    fn shell_pid_in_substitutions() {
+        let mut shell = Shell::new(History::new());
+        run(&mut shell, "x=$(echo $$); y=`echo $$`; z=$( (echo $$) )");
+        let pid = process::id().to_string();
+        for name in ["x", "y", "z"] {
+            assert_eq!(shell.variables.get(name), Some(pid.as_str()), "{name}");
+        }
+    }
+
+    #[test]
+    fn negated_background_job() {
+        let mut shell = Shell::new(History::new());
+        run(
+            &mut shell,
+            "! true & wait $!; x=$?; ! false & wait $!; y=$?",
+        );
+        assert_eq!(shell.variables.get("x"), Some("1"));
+        assert_eq!(shell.variables.get("y"), Some("0"));
+        run(&mut shell, "! true && false & wait $!; z=$?");
+        assert_eq!(shell.variables.get("z"), Some("1"));
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+
+/// Where one of a command's file descriptors points
+#[derive(Clone, Debug)]
+pub enum Fd {
+    /// One of the shell's own standard streams, which are never closed
+    Shell(RawFd),
+    Owned(Arc<OwnedFd>),
+}
+
+impl Fd {
+    pub fn new(fd: impl Into<OwnedFd>) -> Fd {
+        Fd::Owned(Arc::new(fd.into()))
+    }
+
+    fn borrow(&self) -> BorrowedFd<'_> {
+        match self {
+            // SAFETY: the shell's standard streams stay open for as long as it's running
+            Fd::Shell(fd) => unsafe { BorrowedFd::borrow_raw(*fd) },
+            Fd::Owned(fd) => fd.as_fd(),
+        }
+    }
+
+    /// Gives a new descriptor for the same file, numbered high enough that it won't be
+    /// overwritten while a child is moving its descriptors into place
+    fn duplicate(&self) -> io::Result<OwnedFd> {
+        let fd = unsafe { libc::fcntl(self.borrow().as_raw_fd(), libc::F_DUPFD_CLOEXEC, 10) };
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: fcntl just gave us this descriptor and nothing else owns it
+        Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+    }
+}
+
+/// Writes to one of a command's file descriptors, failing the way a real write would if the
+/// descriptor was closed
+pub struct FdWriter(Option<File>);
+
+impl Write for FdWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.0 {
+            Some(file) => file.write(buf),
+            None => Err(io::Error::from_raw_os_error(libc::EBADF)),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.0 {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The file descriptors a command runs with, where any that are missing are closed
+#[derive(Clone, Debug)]
+pub struct Fds {
+    fds: BTreeMap<i32, Fd>,
+}
+
+impl Fds {
+    /// The shell's own stdin, stdout and stderr
+    pub fn standard() -> Fds {
+        let fds = (0..3).map(|fd| (fd, Fd::Shell(fd))).collect();
+        Fds { fds }
+    }
+
+    pub fn set(&mut self, fd: i32, target: Fd) {
+        self.fds.insert(fd, target);
+    }
+
+    pub fn close(&mut self, fd: i32) {
+        self.fds.remove(&fd);
+    }
+
+    /// Points `fd` at whatever `source` points at, like `fd>&source`
+    pub fn duplicate(&mut self, fd: i32, source: i32) -> io::Result<()> {
+        let target = self
+            .fds
+            .get(&source)
+            .cloned()
+            .ok_or_else(|| io::Error::from_raw_os_error(libc::EBADF))?;
+        self.fds.insert(fd, target);
+        Ok(())
+    }
+
+    pub fn writer(&self, fd: i32) -> FdWriter {
+        let file = self
+            .fds
+            .get(&fd)
+            .and_then(|target| target.borrow().try_clone_to_owned().ok())
+            .map(File::from);
+        FdWriter(file)
+    }
+
+    /// Closes every descriptor the process has open apart from these. A forked copy of the shell
+    /// does this so it can't keep pipes belonging to other commands from reaching end of file.
+    pub fn close_others(&self) {
+        let keep: Vec<RawFd> = self
+            .fds
+            .values()
+            .map(|target| target.borrow().as_raw_fd())
+            .collect();
+        let Ok(entries) = fs::read_dir("/dev/fd") else {
+            return;
+        };
+        let open: Vec<RawFd> = entries
+            .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+            .collect();
+
+        for fd in open {
+            if fd > 2 && !keep.contains(&fd) {
+                unsafe { libc::close(fd) };
+            }
+        }
+    }
+
+    /// Sets up a command that's about to be spawned to start with these file descriptors
+    pub fn apply(&self, cmd: &mut Command) -> io::Result<()> {
+        let mut closed = vec![];
+        for fd in 0..3 {
+            let stdio = match self.fds.get(&fd) {
+                Some(target) => Stdio::from(target.duplicate()?),
+                None => {
+                    closed.push(fd);
+                    Stdio::inherit()
+                }
+            };
+            match fd {
+                0 => cmd.stdin(stdio),
+                1 => cmd.stdout(stdio),
+                _ => cmd.stderr(stdio),
+            };
+        }
+
+        let mut extra = vec![];
+        for (fd, target) in self.fds.range(3..) {
+            extra.push((*fd, target.duplicate()?));
+        }
+        if closed.is_empty() && extra.is_empty() {
+            return Ok(());
+        }
+
+        // SAFETY: only async-signal-safe calls are made between fork and exec
+        unsafe {
+            cmd.pre_exec(move || {
+                for (fd, target) in &extra {
+                    if libc::dup2(target.as_raw_fd(), *fd) == -1 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                for fd in &closed {
+                    libc::close(*fd);
+                }
+                Ok(())
+            });
+        }
+        Ok(())
+    }
+}
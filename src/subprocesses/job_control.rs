@@ -0,0 +1,149 @@
+use std::os::fd::RawFd;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::shell::jobs::{Job, State};
+use crate::shell::state::Shell;
+
+/// Signals an interactive shell ignores so it can't be interrupted, stopped or held up by the
+/// terminal, but which the commands it runs need back
+const INTERACTIVE_SIGNALS: [libc::c_int; 5] = [
+    libc::SIGINT,
+    libc::SIGQUIT,
+    libc::SIGTSTP,
+    libc::SIGTTIN,
+    libc::SIGTTOU,
+];
+
+/// Set when Ctrl-C reaches the shell itself, which only happens while it's running builtins
+/// with the terminal to itself
+static SIGINT_RECEIVED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_sigint(_: libc::c_int) {
+    SIGINT_RECEIVED.store(true, Ordering::SeqCst);
+}
+
+/// Notes Ctrl-C rather than ignoring it, so a loop of nothing but builtins can still be stopped.
+/// Anything the shell was waiting on carries on once the handler returns.
+pub fn catch_sigint() {
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = on_sigint as *const () as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        libc::sigaction(libc::SIGINT, &action, std::ptr::null_mut());
+    }
+}
+
+/// Whether Ctrl-C has been pressed since this was last asked, clearing it
+pub fn take_sigint() -> bool {
+    SIGINT_RECEIVED.swap(false, Ordering::SeqCst)
+}
+
+/// The process group a job's processes are put in when job control is on
+#[derive(Clone, Copy, Debug)]
+pub struct ProcessGroup {
+    /// 0 until the job's first process starts the group
+    pub pgid: libc::pid_t,
+    /// Whether the group gets the terminal while it runs
+    pub foreground: bool,
+    pub terminal: RawFd,
+}
+
+impl ProcessGroup {
+    pub fn new(terminal: RawFd, foreground: bool) -> ProcessGroup {
+        ProcessGroup {
+            pgid: 0,
+            foreground,
+            terminal,
+        }
+    }
+
+    /// Puts the calling process in the group. Called in a child between fork and exec, so it
+    /// has to stick to async-signal-safe calls.
+    pub fn join(&self) {
+        unsafe {
+            libc::setpgid(0, self.pgid);
+            if self.foreground {
+                libc::tcsetpgrp(self.terminal, libc::getpgrp());
+            }
+        }
+        reset_signals();
+    }
+
+    /// Records a child that's joined the group from the shell's side. Both sides set it up,
+    /// since there's no telling which of them will get there first.
+    pub fn add(&mut self, pid: libc::pid_t) {
+        if self.pgid == 0 {
+            self.pgid = pid;
+        }
+        unsafe {
+            libc::setpgid(pid, self.pgid);
+            if self.foreground {
+                libc::tcsetpgrp(self.terminal, self.pgid);
+            }
+        }
+    }
+}
+
+/// Gives the signals the shell ignores back their default behaviour, for a child that's about
+/// to become a command
+pub fn reset_signals() {
+    for signal in INTERACTIVE_SIGNALS {
+        unsafe { libc::signal(signal, libc::SIG_DFL) };
+    }
+}
+
+/// Turns on job control when the shell is talking to a terminal, putting the shell in its own
+/// process group in the foreground. Gives back the terminal to hand to jobs.
+pub fn init_job_control() -> Option<RawFd> {
+    if unsafe { libc::isatty(libc::STDIN_FILENO) } == 0 {
+        return None;
+    }
+
+    for signal in INTERACTIVE_SIGNALS {
+        unsafe { libc::signal(signal, libc::SIG_IGN) };
+    }
+    catch_sigint();
+    // Keep our own handle on the terminal, since stdin can be redirected for a command
+    let terminal = unsafe { libc::fcntl(libc::STDIN_FILENO, libc::F_DUPFD_CLOEXEC, 10) };
+    if terminal == -1 {
+        return None;
+    }
+    unsafe {
+        libc::setpgid(0, 0);
+        libc::tcsetpgrp(terminal, libc::getpgrp());
+    }
+    Some(terminal)
+}
+
+/// Waits for a job running in the foreground and takes the terminal back afterwards, first
+/// continuing it if it's being `resume`d. A job that stops goes in the job table so it can be
+/// continued later.
+pub fn wait_for_foreground(shell: &mut Shell, mut job: Job, resume: bool) -> i32 {
+    if let Some(terminal) = shell.terminal
+        && job.pgid != 0
+    {
+        unsafe { libc::tcsetpgrp(terminal, job.pgid) };
+    }
+    if resume {
+        job.resume();
+    }
+
+    let state = job.wait();
+    if let Some(terminal) = shell.terminal {
+        unsafe { libc::tcsetpgrp(terminal, libc::getpgrp()) };
+    }
+
+    let status = job.status();
+    if status == 128 + libc::SIGINT {
+        // The terminal echoed ^C but left the cursor on its line
+        println!();
+        shell.interrupted = true;
+    }
+    if state == State::Stopped {
+        job.reported = State::Stopped;
+        let id = shell.jobs.insert(job);
+        eprintln!("\n{}", shell.jobs.describe(id, false));
+    }
+    status
+}
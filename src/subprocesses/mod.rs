@@ -1 +1,4 @@
+pub mod executor;
+pub mod fds;
+pub mod job_control;
 pub mod utils;
@@ -1,151 +1,103 @@
 use std::env;
-use std::io::{Read, Write};
+use std::io::Write;
 use std::os::unix::fs::PermissionsExt;
-use std::path::{Path, PathBuf};
-use std::process::{ChildStdout, Command, Stdio};
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::PathBuf;
+use std::process::{Command, ExitStatus};
 
 use anyhow::Result;
 
-use crate::input::utils::Redirect;
+use crate::subprocesses::fds::Fds;
+use crate::subprocesses::job_control::{ProcessGroup, reset_signals};
 
-pub enum OutputHandle {
-    ChildPipe(ChildStdout),
-    ChildBuffer(Vec<u8>),
+/// An external program that's been started, or the status it finished with if it couldn't be
+pub enum Spawned {
+    Child(libc::pid_t),
+    Failed(i32),
 }
 
-pub fn path_search(
-    command: &str,
-    verbose: bool,
-    buf: Option<&mut Vec<u8>>,
-    redirect: &Redirect,
-) -> Result<Option<PathBuf>> {
-    let path = env::var("PATH").unwrap();
-    let dirs = env::split_paths(&path);
+/// Converts how a child finished into a shell exit status, where being killed by a signal is
+/// reported as 128 plus the signal number
+pub fn exit_code(status: ExitStatus) -> i32 {
+    match status.code() {
+        Some(code) => code,
+        None => 128 + status.signal().unwrap_or(0),
+    }
+}
+
+pub fn path_search(command: &str, path: &str) -> Result<Option<PathBuf>> {
+    // Commands with a slash in them are paths already, so don't look through $PATH for them
+    let candidates: Vec<PathBuf> = if command.contains('/') {
+        vec![PathBuf::from(command)]
+    } else {
+        env::split_paths(path)
+            .map(|dir| dir.join(command))
+            .collect()
+    };
 
-    for dir in dirs {
-        let path_str = format!("{}/{command}", dir.display());
-        let path = Path::new(&path_str);
+    for candidate in candidates {
+        let path = candidate.as_path();
         if path.exists() {
             let permissions = path.metadata()?.permissions();
             let is_executable = permissions.mode() & 0o111 != 0;
             if is_executable {
-                if verbose {
-                    let exe_is_path = format!("{} is {}\n", command, path.display());
-                    match redirect {
-                        Redirect::Stdout | Redirect::Pipe => {
-                            let buffer = buf.expect("If redirecting we should have a file buffer");
-                            buffer.write_all(exe_is_path.as_bytes())?;
-                        }
-                        _ => print!("{exe_is_path}"),
-                    }
-                }
                 return Ok(Some(path.to_path_buf()));
             }
         }
     }
-
-    if verbose {
-        let not_found = format!("{}: not found\n", command);
-        match redirect {
-            Redirect::Stderr => {
-                let buffer = buf.expect("If redirecting we should have a file buffer");
-                buffer.write_all(not_found.as_bytes())?
-            }
-            _ => print!("{not_found}"),
-        }
-    }
     Ok(None)
 }
 
-pub fn run_program(
+/// Starts an external program with exactly the environment in `env` and the file descriptors
+/// in `fds`, without waiting for it to finish. With job control it goes in the process `group`.
+/// Errors start with `error_prefix`, which says where in a script the command was.
+pub fn spawn_program(
+    error_prefix: &str,
     command: &str,
     arguments: Vec<String>,
-    piped_input: Option<OutputHandle>,
-    buf: &mut Option<&mut Vec<u8>>,
-    redirect: &Redirect,
-) -> Result<Option<OutputHandle>> {
-    let exc_path = path_search(command, false, buf.as_deref_mut(), redirect)?;
-    match exc_path {
-        Some(_) => {
-            let mut cmd = Command::new(command);
-            match redirect {
-                Redirect::Stdout | Redirect::Pipe => cmd.stdout(Stdio::piped()),
-                Redirect::Stderr => cmd.stderr(Stdio::piped()),
-                Redirect::None => cmd.stdout(Stdio::inherit()),
-            };
+    env: &[(String, String)],
+    fds: &Fds,
+    group: Option<&mut ProcessGroup>,
+) -> Result<Spawned> {
+    let path = env
+        .iter()
+        .find(|(name, _)| name == "PATH")
+        .map(|(_, value)| value.as_str())
+        .unwrap_or_default();
+    let Some(exc_path) = path_search(command, path)? else {
+        let _ = writeln!(fds.writer(2), "{error_prefix}{command}: command not found");
+        return Ok(Spawned::Failed(127));
+    };
 
-            let childbuffer = if let Some(outputhandle) = piped_input {
-                match outputhandle {
-                    OutputHandle::ChildPipe(childstdout) => {
-                        let childst = childstdout;
-                        cmd.stdin(childst);
-                        vec![]
-                    }
-                    OutputHandle::ChildBuffer(childbuffer) => {
-                        cmd.stdin(Stdio::piped());
-                        childbuffer
-                    }
-                }
-            } else {
-                vec![]
-            };
-
-            let mut handle = if !arguments.is_empty() {
-                cmd.args(arguments).spawn()?
-            } else {
-                cmd.spawn()?
-            };
-
-            if !childbuffer.is_empty() {
-                let mut stdin = handle.stdin.take().expect("Failed to open stdin");
-                stdin.write_all(&childbuffer)?;
-                drop(stdin);
+    let mut cmd = Command::new(exc_path);
+    cmd.arg0(command)
+        .args(arguments)
+        .env_clear()
+        .envs(env.iter().cloned());
+    fds.apply(&mut cmd)?;
+    let joining = group.as_deref().copied();
+    // SAFETY: joining the group only makes async-signal-safe calls
+    unsafe {
+        cmd.pre_exec(move || {
+            match joining {
+                Some(group) => group.join(),
+                None => reset_signals(),
             }
+            Ok(())
+        });
+    }
 
-            let mut output = Vec::new();
-            match redirect {
-                Redirect::Pipe => {
-                    let stdout = handle.stdout.expect("Should have an output");
-                    return Ok(Some(OutputHandle::ChildPipe(stdout)));
-                }
-                Redirect::Stdout => {
-                    let buffer = buf
-                        .as_deref_mut()
-                        .expect("If redirecting we should have a file buffer");
-                    let mut stdout = handle.stdout.take().expect("Should have an output");
-                    stdout.read_to_end(&mut output)?;
-                    buffer.write_all(&output)?;
-
-                    return Ok(None);
-                }
-                Redirect::Stderr => {
-                    let buffer = buf
-                        .as_deref_mut()
-                        .expect("If redirecting we should have a file buffer");
-                    let mut stderr = handle.stderr.take().expect("Should have an err");
-                    stderr.read_to_end(&mut output)?;
-                    buffer.write_all(&output)?;
-
-                    return Ok(None);
-                }
-                Redirect::None => {
-                    handle.wait()?;
-                    return Ok(None);
-                }
+    match cmd.spawn() {
+        Ok(child) => {
+            let pid = child.id() as libc::pid_t;
+            if let Some(group) = group {
+                group.add(pid);
             }
+            Ok(Spawned::Child(pid))
         }
-        None => {
-            let command_not_found = format!("{}: command not found\n", command);
-            match redirect {
-                Redirect::Stderr => {
-                    let buffer = buf
-                        .as_deref_mut()
-                        .expect("If redirecting we should have a file buffer");
-                    buffer.write_all(command_not_found.as_bytes())?;
-                }
-                _ => print!("{command_not_found}"),
-            }
+        Err(e) => {
+            let _ = writeln!(fds.writer(2), "{error_prefix}{command}: {e}");
+            Ok(Spawned::Failed(126))
         }
     }
-    Ok(None)
 }